@@ -0,0 +1,205 @@
+use std::{collections::HashSet, mem};
+use crate::{diagnostic::Diagnostic, resolve, syntax::{Aexpr, Bexpr, Cmd, Expr, Loc, Program}};
+
+// Definite assignment, checked before a program runs:
+// warns about every use of a variable that some path
+// may reach before the variable is assigned.
+// The check is conservative: a variable is assigned after an `if`
+// only if both branches assign it, and a `while` or `for` body may not run at all.
+// A `do` body runs at least once, up to where it may `break`.
+// Procedure bodies start with their parameters assigned,
+// as well as every global, since the caller may have assigned it.
+
+// The variables definitely assigned at a program point,
+// or `None` where no run gets to (after a jump).
+type Assigned = Option<HashSet<String>>;
+
+struct Checker {
+    targets: HashSet<String>, // variables assigned somewhere
+    breaks: Assigned,         // where the innermost loop may be left
+    continues: Assigned,      // where its body may be cut short
+    diagnostics: Vec<Diagnostic>,
+}
+
+// Variables assigned or bound by `c`.
+fn targets(c: &Cmd, xs: &mut HashSet<String>) {
+    use Cmd::*;
+    match c {
+	Skip (_) | Decl (_, _, _) | AssIndex (_, _, _, _) | Print (_, _) | Return (_, _) |
+	Assert (_, _, _) | Assume (_, _, _) | Break (_) | Continue (_) => (),
+	Ass (x, _, _) | Alloc (x, _, _) | Read (x, _, _) => { xs.insert(x.clone()); }
+	ParAss (ys, _, _) => xs.extend(ys.iter().cloned()),
+	Seq (_, _, _) => for c in c.sequence() { targets(c, xs) },
+	If (_, c1, c2, _) | Loop (c1, c2, _) => { targets(c1, xs); targets(c2, xs) }
+	While (_, c, _) | DoWhile (c, _, _) => targets(c, xs),
+	For (i, _, _, c, _) => { xs.insert(i.clone()); targets(c, xs) }
+	Let (x, _, c, _) => { xs.insert(x.clone()); targets(c, xs) }
+    }
+}
+
+// The variables assigned after either of two paths.
+fn join(a1: Assigned, a2: Assigned) -> Assigned {
+    match (a1, a2) {
+	(None, a) | (a, None) => a,
+	(Some (a1), Some (a2)) => Some (a1.intersection(&a2).cloned().collect())
+    }
+}
+
+impl Checker {
+    fn var(&mut self, x: &str, a: &HashSet<String>, l: Loc) {
+	if a.contains(x) {
+	    return
+	}
+	self.diagnostics.push(
+	    if self.targets.contains(x) {
+		Diagnostic::warning(format!("{} may be used before it is assigned", x))
+		    .with_label(l.0, "possibly unassigned here")
+	    } else {
+		Diagnostic::warning(format!("{} is never assigned", x))
+		    .with_label(l.0, "used here")
+	    })
+    }
+
+    fn aexpr(&mut self, e: &Aexpr, a: &HashSet<String>) {
+	use Aexpr::*;
+	match e {
+	    Int (_, _) | Body (_, _, _) => (),
+	    Var (x, l) | Len (x, l) => self.var(x, a, *l),
+	    Index (x, e, l) => { self.var(x, a, *l); self.aexpr(e, a) }
+	    Neg (_, e, _) => self.aexpr(e, a),
+	    Op (_, _, e1, e2, _) => { self.aexpr(e1, a); self.aexpr(e2, a) }
+	    Cond (b, e1, e2, _) => { self.bexpr(b, a); self.aexpr(e1, a); self.aexpr(e2, a) }
+	    Call (_, es, _) => for e in es { self.aexpr(e, a) }
+	}
+    }
+
+    fn bexpr(&mut self, e: &Bexpr, a: &HashSet<String>) {
+	use Bexpr::*;
+	match e {
+	    Bool (_, _) => (),
+	    Var (x, l) => self.var(x, a, *l),
+	    Not (e, _) => self.bexpr(e, a),
+	    COp (_, e1, e2, _) => { self.aexpr(e1, a); self.aexpr(e2, a) }
+	    BOp (_, e1, e2, _) => { self.bexpr(e1, a); self.bexpr(e2, a) }
+	}
+    }
+
+    fn expr(&mut self, e: &Expr, a: &HashSet<String>) {
+	match e {
+	    Expr::A(e) => self.aexpr(e, a),
+	    Expr::B(e) => self.bexpr(e, a)
+	}
+    }
+
+    // The variables assigned after `c`, given those assigned before.
+    fn cmd(&mut self, c: &Cmd, a: Assigned) -> Assigned {
+	use Cmd::*;
+	let mut a = a?;
+	match c {
+	    Skip (_) | Decl (_, _, _) => (),
+	    Ass (x, e, _) => { self.expr(e, &a); a.insert(x.clone()); }
+	    ParAss (xs, es, _) => {
+		for e in es {
+		    self.expr(e, &a)
+		}
+		a.extend(xs.iter().cloned())
+	    }
+	    Alloc (x, e, _) => { self.aexpr(e, &a); a.insert(x.clone()); }
+	    AssIndex (x, e1, e2, l) => {
+		self.var(x, &a, *l);
+		self.aexpr(e1, &a);
+		self.aexpr(e2, &a)
+	    }
+	    Print (e, _) => self.expr(e, &a),
+	    Assert (e, _, _) | Assume (e, _, _) => self.bexpr(e, &a),
+	    Read (x, _, _) => { a.insert(x.clone()); }
+	    Seq (_, _, _) => return c.sequence().try_fold(a, |a, c| self.cmd(c, Some (a))),
+	    If (e, c1, c2, _) => {
+		self.bexpr(e, &a);
+		let a1 = self.cmd(c1, Some (a.clone()));
+		return join(a1, self.cmd(c2, Some (a)))
+	    }
+	    While (e, c, _) => {
+		self.bexpr(e, &a);
+		self.body(c, Some (a.clone()));
+	    }
+	    For (i, e1, e2, c, _) => {
+		self.aexpr(e1, &a);
+		self.aexpr(e2, &a);
+		let mut b = a.clone();
+		b.insert(i.clone());
+		self.body(c, Some (b));
+	    }
+	    DoWhile (c, e, _) => {
+		let (end, breaks) = self.body(c, Some (a));
+		if let Some (end) = &end {
+		    self.bexpr(e, end)
+		}
+		return join(end, breaks)
+	    }
+	    // Only arises while stepping, after the checks.
+	    Loop (c1, c2, _) => {
+		let (end, _) = self.body(c1, Some (a));
+		return self.cmd(c2, end)
+	    }
+	    Break (_) => { self.breaks = join(self.breaks.take(), Some (a)); return None }
+	    Continue (_) => { self.continues = join(self.continues.take(), Some (a)); return None }
+	    // Assignments to `x` in the body go to the `let` binding.
+	    Let (x, e, c, _) => {
+		self.expr(e, &a);
+		let outer = a.contains(x);
+		a.insert(x.clone());
+		let breaks = self.breaks.take();
+		let continues = self.continues.take();
+		let a = self.cmd(c, Some (a));
+		if !outer {
+		    // Jumps out of the body leave the scope too.
+		    for a in self.breaks.iter_mut().chain(self.continues.iter_mut()) {
+			a.remove(x);
+		    }
+		}
+		self.breaks = join(breaks, self.breaks.take());
+		self.continues = join(continues, self.continues.take());
+		let mut a = a?;
+		if !outer {
+		    a.remove(x);
+		}
+		return Some (a)
+	    }
+	    Return (e, _) => { self.aexpr(e, &a); return None }
+	}
+	Some (a)
+    }
+
+    // Checks a loop body, returning the variables assigned
+    // when it runs to its end or continues, and when it breaks.
+    fn body(&mut self, c: &Cmd, a: Assigned) -> (Assigned, Assigned) {
+	let breaks = self.breaks.take();
+	let continues = self.continues.take();
+	let end = self.cmd(c, a);
+	let end = join(end, mem::replace(&mut self.continues, continues));
+	(end, mem::replace(&mut self.breaks, breaks))
+    }
+}
+
+/// Checks that variables are assigned before they are used,
+/// returning a warning for each use that may not be.
+pub fn check(p: &Program) -> Vec<Diagnostic> {
+    let mut c = Checker {
+	targets: HashSet::new(),
+	breaks: None,
+	continues: None,
+	diagnostics: Vec::new()
+    };
+    for proc in &p.procs {
+	targets(&proc.body, &mut c.targets)
+    }
+    targets(&p.main, &mut c.targets);
+    for proc in &p.procs {
+	let mut a = resolve::globals(&p.main);
+	a.extend(proc.params.iter().cloned());
+	c.cmd(&proc.body, Some (a));
+    }
+    c.cmd(&p.main, Some (HashSet::new()));
+    c.diagnostics
+}
@@ -0,0 +1,29 @@
+use crate::{error::Error, input::Input, limits::Meter, op::{Logic, Overflow}, output::Output, syntax::{Loc, Proc}};
+
+/// Everything a running program can reach besides its store.
+pub struct Context<'a> {
+    pub ov: Overflow,
+    pub logic: Logic,
+    pub procs: &'a [Proc],
+    pub meter: &'a mut Meter,
+    pub out: &'a mut dyn Output,
+    pub inp: &'a mut dyn Input,
+}
+
+impl<'a> Context<'a> {
+    pub fn new(ov: Overflow, logic: Logic, procs: &'a [Proc], meter: &'a mut Meter,
+	       out: &'a mut dyn Output, inp: &'a mut dyn Input) -> Self {
+	Context { ov, logic, procs, meter, out, inp }
+    }
+
+    /// The procedure `p`, called at `l` with `n` arguments.
+    pub fn proc(&self, p: &str, n: usize, l: Loc) -> Result<&'a Proc, Error> {
+	let procs : &'a [Proc] = self.procs;
+	let proc = procs.iter().find(|q| q.name == p)
+	    .ok_or_else(|| Error::UnknownProcedure(String::from(p), l))?;
+	if proc.params.len() != n {
+	    return Err (Error::Arity(String::from(p), proc.params.len(), n, l))
+	}
+	Ok (proc)
+    }
+}
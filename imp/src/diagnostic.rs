@@ -0,0 +1,196 @@
+use std::{fmt, io::{self, Write}};
+use codespan::{ByteSpan, ByteIndex, CodeMap, FileMap, LineIndex};
+
+/// How serious a diagnostic is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Note,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	match self {
+	    Severity::Note    => write!(f, "note"),
+	    Severity::Warning => write!(f, "warning"),
+	    Severity::Error   => write!(f, "error")
+	}
+    }
+}
+
+/// Whether a label marks the cause of a diagnostic
+/// or only gives context for it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LabelStyle {
+    Primary,
+    Secondary,
+}
+
+/// A message attached to a region of source.
+#[derive(Clone, Debug)]
+pub struct Label {
+    pub style: LabelStyle,
+    pub span: ByteSpan,
+    pub message: String,
+}
+
+/// A lexical, parse or runtime error (or warning)
+/// with enough information to point at the source.
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: impl Into<String>) -> Self {
+	Diagnostic {
+	    severity,
+	    message: message.into(),
+	    labels: Vec::new(),
+	    notes: Vec::new()
+	}
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+	Diagnostic::new(Severity::Error, message)
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+	Diagnostic::new(Severity::Warning, message)
+    }
+
+    /// Adds a label pointing at the cause of the diagnostic.
+    pub fn with_label(mut self, span: ByteSpan, message: impl Into<String>) -> Self {
+	self.labels.push(Label {
+	    style: LabelStyle::Primary, span, message: message.into() });
+	self
+    }
+
+    /// Adds a label giving context for the diagnostic.
+    pub fn with_secondary(mut self, span: ByteSpan, message: impl Into<String>) -> Self {
+	self.labels.push(Label {
+	    style: LabelStyle::Secondary, span, message: message.into() });
+	self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+	self.notes.push(note.into());
+	self
+    }
+
+    /// The span of the first primary label, if any.
+    pub fn primary_span(&self) -> Option<ByteSpan> {
+	self.labels.iter()
+	    .find(|l| l.style == LabelStyle::Primary)
+	    .map(|l| l.span)
+    }
+
+    pub fn is_error(&self) -> bool {
+	self.severity == Severity::Error
+    }
+
+    /// Renders the diagnostic rustc-style,
+    /// with a source snippet and carets under each label.
+    pub fn render<W: Write>(&self, codemap: &CodeMap, w: &mut W) -> io::Result<()> {
+	writeln!(w, "{}: {}", self.severity, self.message)?;
+	let located : Vec<(&Label, &FileMap, LineIndex)> =
+	    self.labels.iter()
+	    .filter_map(|l| {
+		let file = codemap.find_file(l.span.start())?;
+		let line = file.find_line(l.span.start()).ok()?;
+		Some ((l, &**file, line))
+	    })
+	    .collect();
+	let width = located.iter()
+	    .map(|(_, _, line)| line.number().to_string().len())
+	    .max()
+	    .unwrap_or(0);
+	let gutter = " ".repeat(width);
+	let head = self.primary_span()
+	    .or_else(|| located.first().map(|(l, _, _)| l.span));
+	if let Some(span) = head {
+	    if let Some(file) = codemap.find_file(span.start()) {
+		if let Ok ((line, col)) = file.location(span.start()) {
+		    writeln!(w, "{}--> {}:{}:{}",
+			     gutter, file.name(), line.number(), col.number())?;
+		    writeln!(w, "{} |", gutter)?;
+		}
+	    }
+	}
+	for (label, file, line) in located.iter() {
+	    let text = line_text(file, *line);
+	    let (_, col) = file.location(label.span.start())
+		.expect("Looking up label start in file.");
+	    let col = col.0 as usize;
+	    // Carets run to the end of the span or the end of the line,
+	    // whichever comes first, and are always at least one wide.
+	    let len = file.src_slice(clamp(file, label.span)).unwrap_or("")
+		.chars()
+		.take_while(|c| *c != '\n')
+		.count()
+		.max(1);
+	    // Keep tabs so that carets line up with the source.
+	    let pad : String = text.chars()
+		.take(col)
+		.map(|c| if c == '\t' { '\t' } else { ' ' })
+		.collect();
+	    let mark = match label.style {
+		LabelStyle::Primary => "^",
+		LabelStyle::Secondary => "-"
+	    };
+	    writeln!(w, "{:>width$} | {}", line.number(), text, width = width)?;
+	    if label.message.is_empty() {
+		writeln!(w, "{} | {}{}", gutter, pad, mark.repeat(len))?;
+	    } else {
+		writeln!(w, "{} | {}{} {}",
+			 gutter, pad, mark.repeat(len), label.message)?;
+	    }
+	}
+	for note in self.notes.iter() {
+	    writeln!(w, "{} = note: {}", gutter, note)?;
+	}
+	Ok (())
+    }
+
+    /// Renders the diagnostic to stderr.
+    pub fn emit(&self, codemap: &CodeMap) {
+	let stderr = io::stderr();
+	let _ = self.render(codemap, &mut stderr.lock());
+    }
+}
+
+/// The text of a line without its line terminator.
+fn line_text(file: &FileMap, line: LineIndex) -> &str {
+    file.line_span(line).ok()
+	.and_then(|span| file.src_slice(span).ok())
+	.unwrap_or("")
+	.trim_end_matches(['\n', '\r'])
+}
+
+/// Shrinks a span so that it lies within a file.
+fn clamp(file: &FileMap, span: ByteSpan) -> ByteSpan {
+    let ByteIndex(end) = file.span().end();
+    let ByteIndex(s) = span.start();
+    let ByteIndex(e) = span.end();
+    ByteSpan::new(ByteIndex(s.min(end)), ByteIndex(e.min(end)))
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	write!(f, "{}: {}", self.severity, self.message)
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+// Lets `?` pass a single diagnostic up
+// where a whole list of them is expected.
+impl From<Diagnostic> for Vec<Diagnostic> {
+    fn from(d: Diagnostic) -> Vec<Diagnostic> {
+	vec![d]
+    }
+}
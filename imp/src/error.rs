@@ -1,14 +1,153 @@
-use std::fmt;
+use std::{fmt, io};
+use crate::{diagnostic::{Diagnostic, Severity}, integer::Integer, limits::{Limit, Partial}, store::Store, syntax::{Aop, Loc}, types::Width};
 
+#[derive(Debug)]
 pub enum Error {
-    UnboundVariable(String)
+    UnboundVariable(String, Loc),
+    Overflow(Aop, Width, Integer, Integer, Loc),
+    NegationOverflow(Width, Integer, Loc),
+    DivisionByZero(Aop, Integer, Loc),
+    LimitExceeded(Limit, Box<Partial>, Loc),
+    Output(io::Error, Loc),
+    EndOfInput(String, Loc),
+    Input(io::Error, Loc),
+    UnknownProcedure(String, Loc),
+    Arity(String, usize, usize, Loc),
+    NoReturn(String, Loc),
+    NotAnInteger(String, Loc),
+    NotABool(String, Loc),
+    NotAnArray(String, Loc),
+    OutOfRange(String, Width, Integer, Loc),
+    IndexOutOfBounds(String, Integer, usize, Loc),
+    BadLength(Integer, Loc),
+    AssertionFailed(String, Loc),
+    Infeasible(String, Loc) // an assumption does not hold
+}
+
+impl Error {
+    /// Where in the source the error arose.
+    pub fn loc(&self) -> Loc {
+	match self {
+	    Error::UnboundVariable(_, l) |
+	    Error::Overflow(_, _, _, _, l) |
+	    Error::NegationOverflow(_, _, l) |
+	    Error::DivisionByZero(_, _, l) |
+	    Error::LimitExceeded(_, _, l) |
+	    Error::Output(_, l) |
+	    Error::EndOfInput(_, l) |
+	    Error::Input(_, l) |
+	    Error::UnknownProcedure(_, l) |
+	    Error::Arity(_, _, _, l) |
+	    Error::NoReturn(_, l) |
+	    Error::NotAnInteger(_, l) |
+	    Error::NotABool(_, l) |
+	    Error::NotAnArray(_, l) |
+	    Error::OutOfRange(_, _, _, l) |
+	    Error::IndexOutOfBounds(_, _, _, l) |
+	    Error::BadLength(_, l) |
+	    Error::AssertionFailed(_, l) |
+	    Error::Infeasible(_, l) => *l
+	}
+    }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 	match self {
-	    Error::UnboundVariable(x) =>
-		write!(f, "Unbound Variable {}", x)
+	    Error::UnboundVariable(x, _) =>
+		write!(f, "Unbound Variable {}", x),
+	    Error::Overflow(o, _, z1, z2, _) =>
+		write!(f, "Integer overflow in {} {} {}", z1, o, z2),
+	    Error::NegationOverflow(_, z, _) =>
+		write!(f, "Integer overflow in -({})", z),
+	    Error::DivisionByZero(o, z, _) =>
+		write!(f, "Division by zero in {} {} 0", z, o),
+	    Error::LimitExceeded(limit, _, _) =>
+		write!(f, "Exceeded the {}", limit),
+	    Error::Output(err, _) =>
+		write!(f, "Could not print: {}", err),
+	    Error::EndOfInput(x, _) =>
+		write!(f, "No input left to read into {}", x),
+	    Error::Input(err, _) =>
+		write!(f, "Could not read: {}", err),
+	    Error::UnknownProcedure(p, _) =>
+		write!(f, "Unknown procedure {}", p),
+	    Error::Arity(p, n, m, _) =>
+		write!(f, "Procedure {} expects {} arguments, found {}", p, n, m),
+	    Error::NoReturn(p, _) =>
+		write!(f, "Procedure {} ended without returning a value", p),
+	    Error::NotAnInteger(x, _) =>
+		write!(f, "{} is not an integer", x),
+	    Error::NotABool(x, _) =>
+		write!(f, "{} is not a bool", x),
+	    Error::NotAnArray(a, _) =>
+		write!(f, "{} is not an array", a),
+	    Error::OutOfRange(x, w, z, _) =>
+		write!(f, "Value {} read into {} does not fit in {}", z, x, w),
+	    Error::IndexOutOfBounds(a, i, n, _) =>
+		write!(f, "Index {} out of bounds for {} of length {}", i, a, n),
+	    Error::BadLength(n, _) =>
+		write!(f, "Invalid array length {}", n),
+	    Error::AssertionFailed(e, _) =>
+		write!(f, "Assertion {} failed", e),
+	    Error::Infeasible(e, _) =>
+		write!(f, "Infeasible path: assumed {}, which does not hold", e)
+	}
+    }
+}
+
+impl std::error::Error for Error {}
+
+// The bindings visible where the run stopped, sorted by name.
+fn visible(s: &Store) -> String {
+    let mut vars : Vec<_> = s.iter().collect();
+    vars.sort_by_key(|(x, _)| *x);
+    vars.iter().map(|(x, z)| format!("{} = {}", x, z)).collect::<Vec<_>>().join(", ")
+}
+
+impl From<Error> for Diagnostic {
+    fn from(err: Error) -> Diagnostic {
+	let text;
+	let label = match &err {
+	    Error::UnboundVariable(..) => "not bound in the store",
+	    Error::Overflow(_, w, ..) |
+	    Error::NegationOverflow(w, ..) => {
+		text = format!("result does not fit in {}", w);
+		&text
+	    }
+	    Error::DivisionByZero(..) => "divisor is zero",
+	    Error::LimitExceeded(..) => "stopped here",
+	    Error::Output(..) => "while printing this",
+	    Error::EndOfInput(..) |
+	    Error::Input(..) => "while reading this",
+	    Error::UnknownProcedure(..) |
+	    Error::Arity(..) |
+	    Error::NoReturn(..) => "in this call",
+	    Error::NotAnInteger(..) => "expected an integer",
+	    Error::NotABool(..) => "expected a bool",
+	    Error::NotAnArray(..) => "expected an array",
+	    Error::OutOfRange(..) => "out of range",
+	    Error::IndexOutOfBounds(..) => "index out of bounds",
+	    Error::BadLength(n, _) => {
+		text = format!("{} is not a valid array length", n);
+		&text
+	    }
+	    Error::AssertionFailed(..) => "assertion is false",
+	    Error::Infeasible(..) => "assumption is false"
+	};
+	// A run that reaches a false assumption is discarded, not failed.
+	let severity = match &err {
+	    Error::Infeasible(..) => Severity::Note,
+	    _ => Severity::Error
+	};
+	let diagnostic = Diagnostic::new(severity, err.to_string())
+	    .with_label(err.loc().0, label);
+	match &err {
+	    Error::LimitExceeded(_, partial, _) => diagnostic
+		.with_note(format!("after {} steps and {} printed lines",
+				   partial.steps, partial.printed))
+		.with_note(format!("store: {{{}}}", visible(&partial.store))),
+	    _ => diagnostic
 	}
     }
 }
@@ -1,78 +1,255 @@
-use crate::{error::Error, store::Store, syntax::{Aexpr, Bexpr, Cmd}};
+use std::collections::HashMap;
+use crate::{context::Context, diagnostic::Diagnostic, error::Error, input, integer::Integer, op::negate, store::{self, Store, Value}, syntax::{Aexpr, Bexpr, Cmd, Expr, Loc}};
 
 impl Aexpr {
-    fn eval(&self, s: &Store) -> Result<i32, Error> {
+    fn eval(&self, s: &mut Store, cx: &mut Context) -> Result<Integer, Error> {
         match self {
-            Aexpr::Int(z) => Ok(*z),
-            Aexpr::Var(x) =>
-		s.get(x)
-		.ok_or(Error::UnboundVariable(String::from(x))),
-            Aexpr::Op(o, e1, e2) => {
-                let z1 = e1.eval(s)?;
-                let z2 = e2.eval(s)?;
-                Ok(o.eval(z1, z2))
+            Aexpr::Int(z, _) => Ok(z.clone()),
+            Aexpr::Var(x, l) => s.int(x, *l),
+	    Aexpr::Index(a, e, l) => {
+		let i = e.eval(s, cx)?;
+		s.element(a, &i, *l)
+	    }
+	    Aexpr::Len(a, l) => Ok(Integer::from(s.array(a, *l)?.len())),
+	    Aexpr::Cond(b, e1, e2, _) => {
+		if b.eval(s, cx)? { e1.eval(s, cx) } else { e2.eval(s, cx) }
+	    }
+            Aexpr::Neg(w, e, l) => {
+		let z = e.eval(s, cx)?;
+		negate(&z, *w, cx.ov, *l)
+	    }
+            Aexpr::Op(o, w, e1, e2, l) => {
+                let z1 = e1.eval(s, cx)?;
+                let z2 = e2.eval(s, cx)?;
+                o.eval(&z1, &z2, *w, cx.ov, *l)
             }
+	    Aexpr::Call(p, es, l) => {
+		let proc = cx.proc(p, es.len(), *l)?;
+		let mut params = HashMap::new();
+		for (x, e) in proc.params.iter().zip(es) {
+		    params.insert(x.clone(), e.eval(s, cx)?);
+		}
+		call(p, &proc.body, params, s, cx, *l)
+	    }
+	    Aexpr::Body(p, c, l) => call(p, c, HashMap::new(), s, cx, *l)
         }
     }
 }
 
+// Runs the body of `p` in a new frame binding `params`.
+fn call(p: &str, body: &Cmd, params: HashMap<String, Integer>,
+	s: &mut Store, cx: &mut Context, l: Loc) -> Result<Integer, Error> {
+    cx.meter.call(s, l)?;
+    let caller = s.enter(params);
+    let z = body.exec(s, cx);
+    s.leave(caller);
+    cx.meter.ret();
+    z?.ok_or_else(|| Error::NoReturn(String::from(p), l))
+}
+
 impl Bexpr {
-    fn eval(&self, s: &Store) -> Result<bool, Error> {
+    fn eval(&self, s: &mut Store, cx: &mut Context) -> Result<bool, Error> {
 	use Bexpr::*;
         match self {
-            Bool(b) => Ok(*b),
-	    Not(e) => {
-		let b = e.eval(s)?;
+            Bool(b, _) => Ok(*b),
+	    Var(x, l) => s.bool(x, *l),
+	    Not(e, _) => {
+		let b = e.eval(s, cx)?;
 		Ok (! b)
 	    }
-	    COp(o, e1, e2) => {
-                let z1 = e1.eval(s)?;
-                let z2 = e2.eval(s)?;
-                Ok(o.eval(z1, z2))
+	    COp(o, e1, e2, _) => {
+                let z1 = e1.eval(s, cx)?;
+                let z2 = e2.eval(s, cx)?;
+                Ok(o.eval(&z1, &z2))
             }
-            BOp(o, e1, e2) => {
-                let b1 = e1.eval(s)?;
-                let b2 = e2.eval(s)?;
+            BOp(o, e1, e2, _) => {
+                let b1 = e1.eval(s, cx)?;
+		if let Some(b) = o.short(b1, cx.logic) {
+		    return Ok(b)
+		}
+                let b2 = e2.eval(s, cx)?;
                 Ok(o.eval(b1, b2))
             }
         }
     }
 }
 
+impl Expr {
+    fn eval(&self, s: &mut Store, cx: &mut Context) -> Result<Value, Error> {
+	match self {
+	    Expr::A(e) => e.eval(s, cx).map(Value::Int),
+	    Expr::B(e) => e.eval(s, cx).map(Value::Bool)
+	}
+    }
+}
+
+// Pending work: a command to run, the end of a `let` scope,
+// or what the innermost loop does once its body is done.
+// `break` and `continue` unwind the work down to the loop.
+enum Work<'a> {
+    Run(&'a Cmd),
+    Exit,
+    Next(Next<'a>),
+}
+
+// How a loop goes on after its body.
+enum Next<'a> {
+    Run(&'a Cmd),       // runs a `while` again, or what follows a stepped loop
+    Test(&'a Bexpr, &'a Cmd), // runs a `do` again if its condition holds
+    Count(&'a str, Integer, Integer, &'a Cmd), // a `for` counter, its bound and body
+}
+
 impl Cmd {
-    pub fn eval(&self, s: &mut Store) -> Result<(), Error> {
-        match self {
-            Cmd::Skip => Ok(()),
-            Cmd::Ass(x, e) => {
-                let z = e.eval(s)?;
-                Ok(s.insert(x, z))
-            }
-	    Cmd::Print(e) => {
-		let z = e.eval(s)?;
-		println!("OUTPUT: {}",z);
-		Ok(())
+    // Commands still to run are kept on an explicit stack,
+    // so neither loop iterations nor long sequences
+    // grow the Rust stack.
+    // Each command taken off the stack counts as one step.
+    // Returns the value of the `return` that ended the run, if any.
+    // However the run ends, it leaves the `let` scopes it entered.
+    fn exec(&self, s: &mut Store, cx: &mut Context) -> Result<Option<Integer>, Error> {
+	let mut work : Vec<Work> = vec![Work::Run(self)];
+	let result = Cmd::work(&mut work, s, cx);
+	for w in work {
+	    if let Work::Exit = w {
+		s.pop();
 	    }
-            Cmd::Seq(c1, c2) => {
-                let _ = c1.eval(s)?;
-                c2.eval(s)
-            }
-            Cmd::If(e, c1, c2) => {
-                let b = e.eval(s)?;
-                if b {
-                    c1.eval(s)
-                } else {
-                    c2.eval(s)
-                }
-            }
-            Cmd::While(e, w) => {
-                let b = e.eval(s)?;
-                if b {
-                    let _ = w.eval(s)?;
-                    self.eval(s)
-                } else {
-                    Ok(())
-                }
+	}
+	result
+    }
+
+    // Runs the work until it is done or a `return` or error ends it.
+    fn work<'a>(work: &mut Vec<Work<'a>>, s: &mut Store, cx: &mut Context) -> Result<Option<Integer>, Error> {
+	while let Some(w) = work.pop() {
+	    let c = match w {
+		Work::Run(c) => c,
+		Work::Exit => { s.pop(); continue }
+		Work::Next(Next::Run(c)) => c,
+		Work::Next(Next::Test(e, c)) => {
+		    if e.eval(s, cx)? {
+			work.push(Work::Run(c))
+		    }
+		    continue
+		}
+		Work::Next(Next::Count(i, z, hi, body)) => {
+		    if z < hi {
+			let z = z.checked_add(&Integer::from(1)).expect("below the bound");
+			s.insert(i, Value::Int(z.clone()));
+			work.push(Work::Next(Next::Count(i, z, hi, body)));
+			work.push(Work::Run(body))
+		    }
+		    continue
+		}
+	    };
+	    cx.meter.step(s, c.loc())?;
+            match c {
+		Cmd::Skip(_) | Cmd::Decl(_, _, _) => (),
+		Cmd::Ass(x, e, _) => {
+                    let v = e.eval(s, cx)?;
+                    s.insert(x, v)
+		}
+		// Every value is computed before any is assigned.
+		Cmd::ParAss(xs, es, _) => {
+		    let vs = es.iter().map(|e| e.eval(s, cx)).collect::<Result<Vec<_>, _>>()?;
+		    for (x, v) in xs.iter().zip(vs) {
+			s.insert(x, v)
+		    }
+		}
+		Cmd::AssIndex(a, e1, e2, l) => {
+		    let i = e1.eval(s, cx)?;
+		    let z = e2.eval(s, cx)?;
+		    s.set_element(a, &i, z, *l)?
+		}
+		Cmd::Alloc(a, e, l) => {
+		    let n = store::length(&e.eval(s, cx)?, *l)?;
+		    cx.meter.alloc(s, n, *l)?;
+		    s.alloc(a, n, *l)?
+		}
+		Cmd::Print(e, l) => {
+		    let v = e.eval(s, cx)?;
+		    cx.meter.print(s, *l)?;
+		    cx.out.print(&v).map_err(|err| Error::Output(err, *l))?
+		}
+		Cmd::Read(x, w, l) => {
+		    let z = input::next(cx.inp, x, *w, *l)?;
+		    s.insert(x, Value::Int(z))
+		}
+		Cmd::Seq(c1, c2, _) => {
+		    work.push(Work::Run(c2));
+		    work.push(Work::Run(c1))
+		}
+		Cmd::If(e, c1, c2, _) => {
+                    let b = e.eval(s, cx)?;
+                    work.push(Work::Run(if b { c1 } else { c2 }))
+		}
+		Cmd::While(e, body, _) => {
+                    let b = e.eval(s, cx)?;
+                    if b {
+			work.push(Work::Next(Next::Run(c)));
+			work.push(Work::Run(body))
+                    }
+		}
+		Cmd::DoWhile(body, e, _) => {
+		    work.push(Work::Next(Next::Test(e, c)));
+		    work.push(Work::Run(body))
+		}
+		Cmd::For(i, e1, e2, body, _) => {
+		    let lo = e1.eval(s, cx)?;
+		    let hi = e2.eval(s, cx)?;
+		    if lo <= hi {
+			s.insert(i, Value::Int(lo.clone()));
+			work.push(Work::Next(Next::Count(i, lo, hi, body)));
+			work.push(Work::Run(body))
+		    }
+		}
+		Cmd::Loop(body, next, _) => {
+		    work.push(Work::Next(Next::Run(next)));
+		    work.push(Work::Run(body))
+		}
+		Cmd::Break(_) | Cmd::Continue(_) => {
+		    while let Some(w) = work.pop() {
+			match w {
+			    Work::Run(_) => (),
+			    Work::Exit => { s.pop(); }
+			    Work::Next(next) => {
+				if let Cmd::Continue(_) = c {
+				    work.push(Work::Next(next))
+				}
+				break
+			    }
+			}
+		    }
+		}
+		Cmd::Let(x, e, body, _) => {
+		    let v = e.eval(s, cx)?;
+		    s.push(x, v);
+		    work.push(Work::Exit);
+		    work.push(Work::Run(body))
+		}
+		Cmd::Return(e, _) => {
+		    let z = e.eval(s, cx)?;
+		    return Ok(Some(z))
+		}
+		Cmd::Assert(e, t, l) => {
+		    if !e.eval(s, cx)? {
+			return Err(Error::AssertionFailed(t.clone(), *l))
+		    }
+		}
+		Cmd::Assume(e, t, l) => {
+		    if !e.eval(s, cx)? {
+			return Err(Error::Infeasible(t.clone(), *l))
+		    }
+		}
             }
-        }
+	}
+	Ok(None)
+    }
+
+    /// Runs the command to completion.
+    pub fn run(&self, s: &mut Store, mut cx: Context) -> Result<(), Error> {
+	self.exec(s, &mut cx).map(|_| ())
+    }
+
+    pub fn eval(&self, s: &mut Store, cx: Context) -> Result<(), Diagnostic> {
+	self.run(s, cx).map_err(Diagnostic::from)
     }
 }
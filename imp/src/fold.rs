@@ -1,107 +1,336 @@
-use crate::syntax::{Aop, Aexpr, Cop, Bop, Bexpr, Cmd};
+use std::collections::HashSet;
+use crate::{diagnostic::Diagnostic, integer::Integer, op::{negate, Logic, Overflow}, syntax::{Aop, Aexpr, Cop, Bop, Bexpr, Cmd, Expr, Loc, Proc, Program}};
 
 // Constant folding.
+// Rewritten nodes keep the location of the node they replace.
+// An expression that may trap at run time
+// (overflow under `Overflow::Trap`, division by zero,
+// an index out of bounds, reading an unbound variable,
+// or anything a procedure call does)
+// is never folded away, so the folded program traps
+// exactly when the original does.
+// A variable counts as bound where every path to it binds it:
+// procedure bodies start with only their parameters bound,
+// and loop bodies may not run.
+// Under short-circuit logic the right operand of `and` and `or`
+// is dropped only where the left one decides the result,
+// so it was never going to be evaluated.
+// Assertions and assumptions folded to `true` are removed;
+// those folded to `false` are kept so they still fail at run time.
+
+// What the folder knows at a program point.
+#[derive(Clone)]
+struct Known {
+    ov: Overflow,
+    logic: Logic,
+    bound: HashSet<String>, // variables bound on every path here
+}
+
+impl Known {
+    fn new(ov: Overflow, logic: Logic, bound: HashSet<String>) -> Self {
+	Known { ov, logic, bound }
+    }
+
+    fn bind(&mut self, x: &str) {
+	self.bound.insert(String::from(x));
+    }
+
+    fn unbound(&self, x: &str) -> bool {
+	!self.bound.contains(x)
+    }
+}
 
 impl Aexpr {
-    fn fold(& self) -> Aexpr {
+    /// Whether evaluating the expression may raise an overflow,
+    /// division-by-zero, bounds or unbound variable error, or call a procedure.
+    fn may_trap(&self, k : &Known) -> bool {
 	use Aexpr::*;
 	match self {
-	    Int (z) => Int (*z),
-	    Var (x) => Var (x.clone()),
-	    Op (o, box e1, box e2) => {
+	    Int (_, _) => false,
+	    Var (x, _) | Len (x, _) => k.unbound(x),
+	    Index (_, _, _) => true,
+	    Neg (_, e, _) => k.ov == Overflow::Trap || e.may_trap(k),
+	    Op (o, _, e1, e2, _) =>
+		k.ov == Overflow::Trap || o.divides()
+		|| e1.may_trap(k) || e2.may_trap(k),
+	    Cond (b, e1, e2, _) => b.may_trap(k) || e1.may_trap(k) || e2.may_trap(k),
+	    Call (_, _, _) | Body (_, _, _) => true
+	}
+    }
+
+    fn fold(& self, k : &Known) -> Aexpr {
+	use Aexpr::*;
+	match self {
+	    Int (z, l) => Int (z.clone(), *l),
+	    Var (x, l) => Var (x.clone(), *l),
+	    Index (a, box e, l) => Index (a.clone(), box e.fold(k), *l),
+	    Len (a, l) => Len (a.clone(), *l),
+	    Neg (w, box e, l) =>
+		match e.fold(k) {
+		    Int (z, l1) => match negate(&z, *w, k.ov, *l) {
+			Ok (n) => Int (n, *l),
+			Err (_) => Neg (*w, box Int (z, l1), *l)
+		    },
+		    e => Neg (*w, box e, *l)
+		},
+	    Op (o, w, box e1, box e2, l) => {
 		use Aop::*;
-		match (*o, e1.fold(), e2.fold()) {
-		    (_, Int (z1), Int (z2))
-			=> Int(o.eval(z1, z2)),
-		    (Add, Int (0),e) |
-		    (Add, e, Int (0)) |
-		    (Mul, Int (1), e) |
-		    (Mul, e, Int (1)) |
-		    (Sub, e, Int (0)) => e,
-		    (Mul, Int (0), _) |
-		    (Mul, _, Int (0)) => Int (0),
+		match (*o, e1.fold(k), e2.fold(k)) {
+		    (_, Int (z1, l1), Int (z2, l2))
+			=> match o.eval(&z1, &z2, *w, k.ov, *l) {
+			    Ok (z) => Int (z, *l),
+			    Err (_) => Op (*o, *w, box Int (z1, l1), box Int (z2, l2), *l)
+			},
+		    (Add, Int (z, _), e) |
+		    (Add, e, Int (z, _)) |
+		    (Sub, e, Int (z, _)) if z.is_zero() => e,
+		    (Mul, Int (z, _), e) |
+		    (Mul, e, Int (z, _)) |
+		    (Div, e, Int (z, _)) if z.is_one() => e,
+		    (Mul, Int (z, _), e) |
+		    (Mul, e, Int (z, _))
+			if z.is_zero() && !e.may_trap(k) => Int (z, *l),
+		    (Mod, e, Int (z, _))
+			if z.is_one() && !e.may_trap(k) => Int (Integer::from(0), *l),
 		    (Sub, e1, e2)
-			=> if e1 == e2 {
-			    Int (0)
+			=> if e1 == e2 && !e1.may_trap(k) {
+			    Int (Integer::from(0), *l)
 			} else {
-			    Op (Sub, box e1, box e2) },
-		    (_, e1, e2) => Op (*o, box e1, box e2)
+			    Op (Sub, *w, box e1, box e2, *l) },
+		    (_, e1, e2) => Op (*o, *w, box e1, box e2, *l)
 		}
 	    }
+	    // Only the arm a constant guard picks is kept.
+	    Cond (box b, box e1, box e2, l) =>
+		match (b.fold(k), e1.fold(k), e2.fold(k)) {
+		    (Bexpr::Bool (true, _), e1, _) => e1,
+		    (Bexpr::Bool (false, _), _, e2) => e2,
+		    (b, e1, e2) => if e1 == e2 && !b.may_trap(k) {
+			e1
+		    } else {
+			Cond (box b, box e1, box e2, *l)
+		    }
+		},
+	    Call (p, es, l) => Call (p.clone(), es.iter().map(|e| e.fold(k)).collect(), *l),
+	    // The body runs in a frame of its own.
+	    Body (p, box c, l) =>
+		Body (p.clone(), box c.fold(&mut Known::new(k.ov, k.logic, HashSet::new())), *l)
 	}
     }
 }
 
 impl Bexpr {
-    fn fold(& self) -> Bexpr {
+    /// Whether evaluating the expression may raise an error or call a procedure.
+    fn may_trap(&self, k : &Known) -> bool {
+	use Bexpr::*;
+	match self {
+	    Bool (_, _) => false,
+	    Var (x, _) => k.unbound(x),
+	    Not (e, _) => e.may_trap(k),
+	    COp (_, e1, e2, _) => e1.may_trap(k) || e2.may_trap(k),
+	    BOp (_, e1, e2, _) => e1.may_trap(k) || e2.may_trap(k)
+	}
+    }
+
+    fn fold(& self, k : &Known) -> Bexpr {
 	use Bexpr::*;
 	match self {
-	    Bool (b) => Bool (*b),
-	    Not (box e) =>
-		match e.fold() {
-		    Bool (b) => Bool (!b),
-		    e => Not (box e)
+	    Bool (b, l) => Bool (*b, *l),
+	    Var (x, l) => Var (x.clone(), *l),
+	    Not (box e, l) =>
+		match e.fold(k) {
+		    Bool (b, _) => Bool (!b, *l),
+		    e => Not (box e, *l)
 		},
-	    COp (o, box e1, box e2) => {
+	    COp (o, box e1, box e2, l) => {
 		use Cop::*;
 		use Aexpr::*;
-		match (*o, e1.fold(), e2.fold()) {
-		    (_, Int(z1), Int(z2)) => Bool (o.eval(z1,z2)),
-		    (Eq, e1, e2) =>
-			if e1 == e2 {
-			    Bool (true)
-			} else {
-			    COp (Eq, box e1, box e2) },
-		    (_, e1, e2) => COp (*o, box e1, box e2)
+		match (*o, e1.fold(k), e2.fold(k)) {
+		    (_, Int(z1, _), Int(z2, _)) => Bool (o.eval(&z1,&z2), *l),
+		    // Comparing an expression with itself.
+		    (o, e1, e2) if e1 == e2 && !e1.may_trap(k) =>
+			Bool (matches!(o, Eq | Le | Ge), *l),
+		    (o, e1, e2) => COp (o, box e1, box e2, *l)
 		}
 	    }
-	    BOp (o, box e1, box e2) => {
+	    BOp (o, box e1, box e2, l) => {
 		use Bop::*;
-		match (*o, e1.fold(), e2.fold()) {
-		    (_, Bool (b1), Bool (b2)) => Bool (o.eval(b1,b2)),
-		    (And, Bool (true), e) |
-		    (And, e, Bool (true)) |
-		    (Or, Bool (false), e) |
-		    (Or, e, Bool (false)) => e,
-		    (And, e @ Bool (false), _) |
-		    (And, _, e @ Bool (false)) |
-		    (Or, e @ Bool (true), _) |
-		    (Or, _, e @ Bool (true)) => e,
+		match (*o, e1.fold(k), e2.fold(k)) {
+		    (_, Bool (b1, _), Bool (b2, _)) => Bool (o.eval(b1,b2), *l),
+		    // The right operand is never evaluated.
+		    (o, Bool (b, _), _) if o.short(b, k.logic).is_some() => Bool (b, *l),
+		    (And, Bool (true, _), e) |
+		    (And, e, Bool (true, _)) |
+		    (Or, Bool (false, _), e) |
+		    (Or, e, Bool (false, _)) => e,
+		    (And, Bool (false, _), e) |
+		    (And, e, Bool (false, _)) if !e.may_trap(k) => Bool (false, *l),
+		    (Or, Bool (true, _), e) |
+		    (Or, e, Bool (true, _)) if !e.may_trap(k) => Bool (true, *l),
 		    (_, e1, e2)
-			=> if e1 == e2 { e1 } else { BOp (*o, box e1, box e2) }
+			=> if e1 == e2 && !e1.may_trap(k) { e1 } else { BOp (*o, box e1, box e2, *l) }
 		}
 	    }
 	}
     }
 }
 
+impl Expr {
+    fn may_trap(&self, k : &Known) -> bool {
+	match self {
+	    Expr::A(e) => e.may_trap(k),
+	    Expr::B(e) => e.may_trap(k)
+	}
+    }
+
+    fn fold(& self, k : &Known) -> Expr {
+	match self {
+	    Expr::A(e) => Expr::A(e.fold(k)),
+	    Expr::B(e) => Expr::B(e.fold(k))
+	}
+    }
+}
+
 impl Cmd {
-    pub fn fold (& self) -> Cmd {
+    // Folds the command, and binds in `k` what it binds.
+    fn fold (& self, k : &mut Known) -> Cmd {
 	use Cmd::*;
 	match self {
-	    Skip => Skip,
-	    Ass (x, box e) => Ass (x.clone(), box e.fold()),
-	    Print (box e) => Print (box e.fold()),
-	    Seq (box c1, box c2) =>
-		match (c1.fold(), c2.fold()) {
-		    (Skip, c) |
-		    (c, Skip) => c,
-		    (c1, c2) => Seq (box c1, box c2)
+	    Skip (l) => Skip (*l),
+	    Decl (x, t, l) => Decl (x.clone(), *t, *l),
+	    Ass (x, box e, l) => {
+		let e = e.fold(k);
+		k.bind(x);
+		Ass (x.clone(), box e, *l)
+	    }
+	    ParAss (xs, es, l) => {
+		let es = es.iter().map(|e| e.fold(k)).collect();
+		for x in xs { k.bind(x) }
+		ParAss (xs.clone(), es, *l)
+	    }
+	    AssIndex (a, box e1, box e2, l) =>
+		AssIndex (a.clone(), box e1.fold(k), box e2.fold(k), *l),
+	    Alloc (a, box e, l) => {
+		let e = e.fold(k);
+		k.bind(a);
+		Alloc (a.clone(), box e, *l)
+	    }
+	    Print (box e, l) => Print (box e.fold(k), *l),
+	    Read (x, w, l) => { k.bind(x); Read (x.clone(), *w, *l) }
+	    // Folded from first to last, then put back together from the end.
+	    Seq (_, _, _) => {
+		let mut cs : Vec<(Cmd, Loc)> = Vec::new();
+		let mut c = self;
+		while let Seq (c1, c2, l) = c {
+		    cs.push((c1.fold(k), *l));
+		    c = c2
 		}
-	    If (box e, box c1, box c2)
-		=> match e.fold() {
-		    Bexpr::Bool (b) => if b { c1.fold() } else { c2.fold() },
-		    e => if c1 == c2 {
-			c1.fold()
-		    } else {
-			If (box e, box c1.fold(), box c2.fold())
+		let last = c.fold(k);
+		cs.into_iter().rev().fold(last, |c2, (c1, l)| match (c1, c2) {
+		    (Skip (_), c) |
+		    (c, Skip (_)) => c,
+		    (c1, c2) => Seq (box c1, box c2, l)
+		})
+	    }
+	    If (box e, box c1, box c2, l)
+		=> match e.fold(k) {
+		    Bexpr::Bool (b, _) => if b { c1.fold(k) } else { c2.fold(k) },
+		    e => {
+			let same = c1 == c2 && !e.may_trap(k);
+			let mut k2 = k.clone();
+			let (c1, c2) = (c1.fold(k), c2.fold(&mut k2));
+			k.bound.retain(|x| k2.bound.contains(x));
+			if same { c1 } else { If (box e, box c1, box c2, *l) }
 		    }
 		},
-	    While (box e, box c)
-		=> match e.fold() {
-		    Bexpr::Bool (false) => Skip,
-		    e => While (box e, box c.fold())
+	    While (box e, box c, l)
+		=> match e.fold(k) {
+		    Bexpr::Bool (false, _) => Skip (*l),
+		    e => While (box e, box c.fold(&mut k.clone()), *l)
+		},
+	    For (i, box e1, box e2, box c, l)
+		=> match (e1.fold(k), e2.fold(k)) {
+		    (Aexpr::Int (lo, _), Aexpr::Int (hi, _)) if lo > hi => Skip (*l),
+		    (e1, e2) => {
+			let mut body = k.clone();
+			body.bind(i);
+			For (i.clone(), box e1, box e2, box c.fold(&mut body), *l)
+		    }
+		},
+	    // Only what is bound before the loop is sure to be bound
+	    // when the condition is tested.
+	    DoWhile (box c, box e, l) => DoWhile (box c.fold(&mut k.clone()), box e.fold(k), *l),
+	    Loop (box c1, box c2, l) =>
+		Loop (box c1.fold(&mut k.clone()), box c2.fold(&mut k.clone()), *l),
+	    Break (l) => Break (*l),
+	    Continue (l) => Continue (*l),
+	    Let (x, box e, box c, l) => {
+		let e = e.fold(k);
+		let pure = !e.may_trap(k);
+		let outer = !k.unbound(x);
+		let mut body = k.clone();
+		body.bind(x);
+		let c = c.fold(&mut body);
+		*k = body;
+		if !outer {
+		    k.bound.remove(x);
+		}
+		match (e, c) {
+		    (_, Skip (_)) if pure => Skip (*l),
+		    (e, c) => Let (x.clone(), box e, box c, *l)
+		}
+	    }
+	    Return (box e, l) => Return (box e.fold(k), *l),
+	    Assert (box e, t, l)
+		=> match e.fold(k) {
+		    Bexpr::Bool (true, _) => Skip (*l),
+		    e => Assert (box e, t.clone(), *l)
+		},
+	    Assume (box e, t, l)
+		=> match e.fold(k) {
+		    Bexpr::Bool (true, _) => Skip (*l),
+		    e => Assume (box e, t.clone(), *l)
 		}
 	}
     }
+
+    // Warns about each assertion in a folded command
+    // whose condition folded to `false`.
+    fn refuted(&self, diagnostics : &mut Vec<Diagnostic>) {
+	use Cmd::*;
+	match self {
+	    Assert (box Bexpr::Bool (false, _), t, l) => diagnostics.push(
+		Diagnostic::warning(format!("Assertion {} always fails", t))
+		    .with_label(l.0, "false whenever it is reached")),
+	    Seq (_, _, _) => for c in self.sequence() { c.refuted(diagnostics) },
+	    If (_, c1, c2, _) | Loop (c1, c2, _) => {
+		c1.refuted(diagnostics);
+		c2.refuted(diagnostics)
+	    }
+	    While (_, c, _) | For (_, _, _, c, _) | DoWhile (c, _, _) | Let (_, _, c, _) =>
+		c.refuted(diagnostics),
+	    _ => ()
+	}
+    }
+}
+
+impl Program {
+    pub fn fold (& self, ov : Overflow, logic : Logic) -> Program {
+	let procs = self.procs.iter().map(|p| {
+	    let mut k = Known::new(ov, logic, p.params.iter().cloned().collect());
+	    Proc { name: p.name.clone(), params: p.params.clone(), body: p.body.fold(&mut k), loc: p.loc }
+	}).collect();
+	let mut k = Known::new(ov, logic, HashSet::new());
+	Program { procs, main: self.main.fold(&mut k) }
+    }
+
+    /// Warns about the assertions a folded program can be seen to violate.
+    pub fn refuted (& self) -> Vec<Diagnostic> {
+	let mut diagnostics = Vec::new();
+	for p in &self.procs {
+	    p.body.refuted(&mut diagnostics)
+	}
+	self.main.refuted(&mut diagnostics);
+	diagnostics
+    }
 }
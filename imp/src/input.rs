@@ -0,0 +1,70 @@
+use std::{collections::VecDeque, io::{self, BufRead}, str::FromStr};
+use crate::{error::Error, integer::{Integer, ParseIntegerError}, op::Overflow, syntax::Loc, types::Width};
+
+// Where `read` takes its values from.
+// `Ok (None)` means the input is exhausted.
+pub trait Input {
+    fn read(&mut self) -> io::Result<Option<Integer>>;
+}
+
+/// The next value to read into `x` of width `w` at `l`.
+/// Values that do not fit are rejected whatever the overflow policy.
+pub fn next(inp: &mut dyn Input, x: &str, w: Width, l: Loc) -> Result<Integer, Error> {
+    match inp.read() {
+	Ok (Some (z)) => w.fit(z.clone(), Overflow::Trap)
+	    .ok_or_else(|| Error::OutOfRange(String::from(x), w, z, l)),
+	Ok (None) => Err (Error::EndOfInput(String::from(x), l)),
+	Err (err) => Err (Error::Input(err, l))
+    }
+}
+
+/// Reads whitespace-separated integers from stdin.
+#[derive(Default)]
+pub struct Stdin {
+    pending: VecDeque<String>,
+}
+
+impl Input for Stdin {
+    fn read(&mut self) -> io::Result<Option<Integer>> {
+	while self.pending.is_empty() {
+	    let mut line = String::new();
+	    if io::stdin().lock().read_line(&mut line)? == 0 {
+		return Ok (None)
+	    }
+	    self.pending.extend(line.split_whitespace().map(String::from))
+	}
+	let word = self.pending.pop_front().unwrap();
+	word.parse().map(Some).map_err(|err : ParseIntegerError|
+	    io::Error::new(io::ErrorKind::InvalidData,
+			   format!("{} is not an integer: {}", word, err)))
+    }
+}
+
+/// A fixed queue of values, e.g. from `--input 1,2,3`.
+#[derive(Clone, Debug, Default)]
+pub struct Queue(pub VecDeque<Integer>);
+
+impl Input for Queue {
+    fn read(&mut self) -> io::Result<Option<Integer>> {
+	Ok (self.0.pop_front())
+    }
+}
+
+impl From<Vec<Integer>> for Queue {
+    fn from(zs: Vec<Integer>) -> Self {
+	Queue(zs.into())
+    }
+}
+
+/// Parses a comma-separated list such as `1,-2,3`.
+impl FromStr for Queue {
+    type Err = ParseIntegerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+	s.split(',')
+	    .filter(|z| !z.trim().is_empty())
+	    .map(|z| z.trim().parse())
+	    .collect::<Result<VecDeque<_>, _>>()
+	    .map(Queue)
+    }
+}
@@ -0,0 +1,232 @@
+use std::{convert::TryFrom, fmt, str::FromStr};
+
+// Imp integers, the values of every integer type.
+// `types::Width` gives the types and their ranges.
+// They are held in an `i64`, wide enough for every type,
+// or a `BigInt` with the `bigint` feature.
+// Arithmetic is narrowed to the width of its type by `types::Width::fit`.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Integer(Repr);
+
+#[cfg(not(feature = "bigint"))]
+type Repr = i64;
+
+#[cfg(feature = "bigint")]
+type Repr = num_bigint::BigInt;
+
+/// Error for a literal that is not an integer.
+#[cfg(not(feature = "bigint"))]
+pub type ParseIntegerError = std::num::ParseIntError;
+
+/// Error for a literal that is not an integer.
+#[cfg(feature = "bigint")]
+pub type ParseIntegerError = num_bigint::ParseBigIntError;
+
+impl Integer {
+    pub fn is_zero(&self) -> bool {
+	*self == Integer::from(0)
+    }
+
+    pub fn is_one(&self) -> bool {
+	*self == Integer::from(1)
+    }
+}
+
+#[cfg(not(feature = "bigint"))]
+impl Integer {
+    /// The integer as an array index or length, if it is one.
+    pub fn to_usize(&self) -> Option<usize> {
+	usize::try_from(self.0).ok()
+    }
+
+    pub fn checked_neg(&self) -> Option<Integer> {
+	self.0.checked_neg().map(Integer)
+    }
+
+    pub fn wrapping_neg(&self) -> Integer {
+	Integer(self.0.wrapping_neg())
+    }
+
+    pub fn saturating_neg(&self) -> Integer {
+	Integer(self.0.saturating_neg())
+    }
+
+    pub fn checked_add(&self, other: &Integer) -> Option<Integer> {
+	self.0.checked_add(other.0).map(Integer)
+    }
+
+    pub fn checked_sub(&self, other: &Integer) -> Option<Integer> {
+	self.0.checked_sub(other.0).map(Integer)
+    }
+
+    pub fn checked_mul(&self, other: &Integer) -> Option<Integer> {
+	self.0.checked_mul(other.0).map(Integer)
+    }
+
+    pub fn wrapping_add(&self, other: &Integer) -> Integer {
+	Integer(self.0.wrapping_add(other.0))
+    }
+
+    pub fn wrapping_sub(&self, other: &Integer) -> Integer {
+	Integer(self.0.wrapping_sub(other.0))
+    }
+
+    pub fn wrapping_mul(&self, other: &Integer) -> Integer {
+	Integer(self.0.wrapping_mul(other.0))
+    }
+
+    pub fn saturating_add(&self, other: &Integer) -> Integer {
+	Integer(self.0.saturating_add(other.0))
+    }
+
+    pub fn saturating_sub(&self, other: &Integer) -> Integer {
+	Integer(self.0.saturating_sub(other.0))
+    }
+
+    pub fn saturating_mul(&self, other: &Integer) -> Integer {
+	Integer(self.0.saturating_mul(other.0))
+    }
+
+    // Division and remainder truncate towards zero.
+    // The divisor must not be zero.
+
+    pub fn checked_div(&self, other: &Integer) -> Option<Integer> {
+	self.0.checked_div(other.0).map(Integer)
+    }
+
+    pub fn wrapping_div(&self, other: &Integer) -> Integer {
+	Integer(self.0.wrapping_div(other.0))
+    }
+
+    pub fn saturating_div(&self, other: &Integer) -> Integer {
+	Integer(self.0.saturating_div(other.0))
+    }
+
+    /// Never overflows: `i64::MIN % -1` is `0`.
+    pub fn rem(&self, other: &Integer) -> Integer {
+	Integer(self.0.wrapping_rem(other.0))
+    }
+
+    /// Wraps the integer around into the range `lo..=hi`.
+    pub fn wrap(&self, lo: &Integer, hi: &Integer) -> Integer {
+	let n = hi.0 as i128 - lo.0 as i128 + 1;
+	Integer((lo.0 as i128 + (self.0 as i128 - lo.0 as i128).rem_euclid(n)) as i64)
+    }
+}
+
+// Arbitrary-precision arithmetic is exact,
+// so every overflow policy computes the same result.
+#[cfg(feature = "bigint")]
+impl Integer {
+    pub fn to_usize(&self) -> Option<usize> {
+	usize::try_from(&self.0).ok()
+    }
+
+    pub fn checked_neg(&self) -> Option<Integer> {
+	Some (Integer(-&self.0))
+    }
+
+    pub fn wrapping_neg(&self) -> Integer {
+	Integer(-&self.0)
+    }
+
+    pub fn saturating_neg(&self) -> Integer {
+	Integer(-&self.0)
+    }
+
+    pub fn checked_add(&self, other: &Integer) -> Option<Integer> {
+	Some (Integer(&self.0 + &other.0))
+    }
+
+    pub fn checked_sub(&self, other: &Integer) -> Option<Integer> {
+	Some (Integer(&self.0 - &other.0))
+    }
+
+    pub fn checked_mul(&self, other: &Integer) -> Option<Integer> {
+	Some (Integer(&self.0 * &other.0))
+    }
+
+    pub fn wrapping_add(&self, other: &Integer) -> Integer {
+	Integer(&self.0 + &other.0)
+    }
+
+    pub fn wrapping_sub(&self, other: &Integer) -> Integer {
+	Integer(&self.0 - &other.0)
+    }
+
+    pub fn wrapping_mul(&self, other: &Integer) -> Integer {
+	Integer(&self.0 * &other.0)
+    }
+
+    pub fn saturating_add(&self, other: &Integer) -> Integer {
+	Integer(&self.0 + &other.0)
+    }
+
+    pub fn saturating_sub(&self, other: &Integer) -> Integer {
+	Integer(&self.0 - &other.0)
+    }
+
+    pub fn saturating_mul(&self, other: &Integer) -> Integer {
+	Integer(&self.0 * &other.0)
+    }
+
+    // Division and remainder truncate towards zero.
+    // The divisor must not be zero.
+
+    pub fn checked_div(&self, other: &Integer) -> Option<Integer> {
+	Some (Integer(&self.0 / &other.0))
+    }
+
+    pub fn wrapping_div(&self, other: &Integer) -> Integer {
+	Integer(&self.0 / &other.0)
+    }
+
+    pub fn saturating_div(&self, other: &Integer) -> Integer {
+	Integer(&self.0 / &other.0)
+    }
+
+    pub fn rem(&self, other: &Integer) -> Integer {
+	Integer(&self.0 % &other.0)
+    }
+
+    /// Wraps the integer around into the range `lo..=hi`.
+    pub fn wrap(&self, lo: &Integer, hi: &Integer) -> Integer {
+	let n = &hi.0 - &lo.0 + 1;
+	let r = (&self.0 - &lo.0) % &n;
+	let r = if r < Repr::from(0) { r + n } else { r };
+	Integer(&lo.0 + r)
+    }
+}
+
+impl From<i32> for Integer {
+    fn from(z: i32) -> Self {
+	Integer(Repr::from(z))
+    }
+}
+
+impl From<i64> for Integer {
+    fn from(z: i64) -> Self {
+	Integer(Repr::from(z))
+    }
+}
+
+// Array lengths, which are made from integers and so always fit.
+impl From<usize> for Integer {
+    fn from(n: usize) -> Self {
+	Integer(Repr::try_from(n).unwrap())
+    }
+}
+
+impl FromStr for Integer {
+    type Err = ParseIntegerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+	s.parse::<Repr>().map(Integer)
+    }
+}
+
+impl fmt::Display for Integer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	write!(f, "{}", self.0)
+    }
+}
@@ -1,6 +1,7 @@
 use std::{char, fmt, sync::Arc, str::CharIndices, iter::{Iterator, Peekable}};
 use peeking_take_while::PeekableExt;
-use codespan::{FileMap, ByteIndex, ByteOffset, LineIndex, ColumnIndex};
+use codespan::{FileMap, ByteIndex, ByteOffset, ByteSpan};
+use crate::{diagnostic::Diagnostic, integer::{Integer, ParseIntegerError}};
 
 #[derive(Clone)]
 pub enum Token {
@@ -8,28 +9,56 @@ pub enum Token {
     RBRACE,
     LPAREN,
     RPAREN,
+    LBRACKET,
+    RBRACKET,
     SEMICOLON,
+    COLON,
+    COMMA,
     IF,
+    THEN,
     ELSE,
     WHILE,
+    FOR,
+    TO,
+    DO,
+    BREAK,
+    CONTINUE,
     ASGN,
     PRINT,
+    READ,
+    LET,
+    IN,
+    PROC,
+    RETURN,
+    ASSERT,
+    ASSUME,
+    ARRAY,
+    LEN,
     SKIP,
     OR,
     AND,
     EQ,
+    NE,
     LT,
+    LE,
+    GT,
+    GE,
     NOT,
     ADD,
     SUB,
     MUL,
+    DIV,
+    MOD,
     BOOL(bool),
-    NUM(i32),
-    VAR(String)
+    NUM(Integer),
+    VAR(String),
+    COMMENT(String) // only produced when keeping comments.
 }
 
 impl Token {
     /// Lexical length of a token.
+    /// A numeral may be written longer than it prints, as in `007`,
+    /// so `Lexer::number` spans numerals by their text instead.
     fn len(&self) -> usize {
 	use Token::*;
 	match self {
@@ -37,25 +66,51 @@ impl Token {
 	    RBRACE |
 	    LPAREN |
 	    RPAREN |
+	    LBRACKET |
+	    RBRACKET |
 	    ADD    |
 	    SUB    |
 	    MUL    |
+	    DIV    |
+	    MOD    |
 	    NOT    |
-	    SEMICOLON => 1,
+	    SEMICOLON |
+	    COLON |
+	    COMMA => 1,
 	    OR  |
 	    EQ  |
 	    LT  |
+	    GT  |
 	    IF  |
+	    IN  |
+	    TO  |
+	    DO  |
 	    ASGN => 2,
-	    AND  => 3,
+	    AND |
+	    NE  |
+	    LE  |
+	    GE  |
+	    LET |
+	    FOR |
+	    LEN  => 3,
 	    ELSE |
+	    THEN |
 	    SKIP |
+	    READ |
+	    PROC |
 	    BOOL (true) => 4,
 	    WHILE |
 	    PRINT |
+	    ARRAY |
+	    BREAK |
 	    BOOL (false) => 5,
+	    RETURN |
+	    ASSERT |
+	    ASSUME => 6,
+	    CONTINUE => 8,
 	    NUM(z) => z.to_string().len(),
-	    VAR(x) => x.len()
+	    VAR(x) => x.len(),
+	    COMMENT(s) => s.len()
 	}
     }
 }
@@ -68,24 +123,50 @@ impl fmt::Display for Token {
 	    RBRACE    => write!(f,"{}","}"),
 	    LPAREN    => write!(f,"("),
 	    RPAREN    => write!(f,")"),
+	    LBRACKET  => write!(f,"["),
+	    RBRACKET  => write!(f,"]"),
 	    SEMICOLON => write!(f,";"),
+	    COLON     => write!(f,":"),
+	    COMMA     => write!(f,","),
 	    IF        => write!(f,"if"),
+	    THEN      => write!(f,"then"),
 	    ELSE      => write!(f,"else"),
 	    WHILE     => write!(f,"while"),
+	    FOR       => write!(f,"for"),
+	    TO        => write!(f,"to"),
+	    DO        => write!(f,"do"),
+	    BREAK     => write!(f,"break"),
+	    CONTINUE  => write!(f,"continue"),
 	    ASGN      => write!(f,":="),
 	    PRINT     => write!(f,"print"),
+	    READ      => write!(f,"read"),
+	    LET       => write!(f,"let"),
+	    IN        => write!(f,"in"),
+	    PROC      => write!(f,"proc"),
+	    RETURN    => write!(f,"return"),
+	    ASSERT    => write!(f,"assert"),
+	    ASSUME    => write!(f,"assume"),
+	    ARRAY     => write!(f,"array"),
+	    LEN       => write!(f,"len"),
 	    SKIP      => write!(f,"skip"),
 	    OR        => write!(f,"or"),
 	    AND       => write!(f,"and"),
 	    EQ        => write!(f,"=?"),
+	    NE        => write!(f,"!=?"),
 	    LT        => write!(f,"<?"),
+	    LE        => write!(f,"<=?"),
+	    GT        => write!(f,">?"),
+	    GE        => write!(f,">=?"),
 	    NOT       => write!(f,"!"),
 	    ADD       => write!(f,"+"),
 	    SUB       => write!(f,"-"),
 	    MUL       => write!(f,"*"),
+	    DIV       => write!(f,"/"),
+	    MOD       => write!(f,"%"),
 	    BOOL(b)   => write!(f,"{}",b),
 	    NUM(z)    => write!(f,"{}",z),
-	    VAR(x)    => write!(f,"{}",x)
+	    VAR(x)    => write!(f,"{}",x),
+	    COMMENT(s) => write!(f,"{}",s)
 	}
     }
 }
@@ -98,24 +179,50 @@ impl fmt::Debug for Token {
 	    RBRACE    => write!(f,"{}","}"),
 	    LPAREN    => write!(f,"("),
 	    RPAREN    => write!(f,")"),
+	    LBRACKET  => write!(f,"["),
+	    RBRACKET  => write!(f,"]"),
 	    SEMICOLON => write!(f,";"),
+	    COLON     => write!(f,":"),
+	    COMMA     => write!(f,","),
 	    IF        => write!(f,"if"),
+	    THEN      => write!(f,"then"),
 	    ELSE      => write!(f,"else"),
 	    WHILE     => write!(f,"while"),
+	    FOR       => write!(f,"for"),
+	    TO        => write!(f,"to"),
+	    DO        => write!(f,"do"),
+	    BREAK     => write!(f,"break"),
+	    CONTINUE  => write!(f,"continue"),
 	    ASGN      => write!(f,":="),
 	    PRINT     => write!(f,"print"),
+	    READ      => write!(f,"read"),
+	    LET       => write!(f,"let"),
+	    IN        => write!(f,"in"),
+	    PROC      => write!(f,"proc"),
+	    RETURN    => write!(f,"return"),
+	    ASSERT    => write!(f,"assert"),
+	    ASSUME    => write!(f,"assume"),
+	    ARRAY     => write!(f,"array"),
+	    LEN       => write!(f,"len"),
 	    SKIP      => write!(f,"skip"),
 	    OR        => write!(f,"or"),
 	    AND       => write!(f,"and"),
 	    EQ        => write!(f,"=?"),
+	    NE        => write!(f,"!=?"),
 	    LT        => write!(f,"<?"),
+	    LE        => write!(f,"<=?"),
+	    GT        => write!(f,">?"),
+	    GE        => write!(f,">=?"),
 	    NOT       => write!(f,"!"),
 	    ADD       => write!(f,"+"),
 	    SUB       => write!(f,"-"),
 	    MUL       => write!(f,"*"),
+	    DIV       => write!(f,"/"),
+	    MOD       => write!(f,"%"),
 	    BOOL(b)   => write!(f,"{}",b),
 	    NUM(z)    => write!(f,"{}",z),
-	    VAR(x)    => write!(f,"{}",x)
+	    VAR(x)    => write!(f,"{}",x),
+	    COMMENT(s) => write!(f,"{}",s)
 	}
     }
 }
@@ -124,8 +231,23 @@ fn token_of_string (s : &str) -> Token {
     match s {
 	"if"    => Token::IF,
 	"else"  => Token::ELSE,
+	"then"  => Token::THEN,
 	"while" => Token::WHILE,
+	"for"   => Token::FOR,
+	"to"    => Token::TO,
+	"do"    => Token::DO,
+	"break" => Token::BREAK,
+	"continue" => Token::CONTINUE,
 	"print" => Token::PRINT,
+	"read"  => Token::READ,
+	"let"   => Token::LET,
+	"in"    => Token::IN,
+	"proc"  => Token::PROC,
+	"return" => Token::RETURN,
+	"assert" => Token::ASSERT,
+	"assume" => Token::ASSUME,
+	"array" => Token::ARRAY,
+	"len"   => Token::LEN,
 	"skip"  => Token::SKIP,
 	"and"   => Token::AND,
 	"or"    => Token::OR,
@@ -146,7 +268,8 @@ pub type Spanned = (
 pub enum BadLex {
     NonTokenChar(char),
     ExpectedChar(char,char),
-    Internal(std::num::ParseIntError)
+    UnterminatedComment,
+    Internal(ParseIntegerError)
 }
 
 impl fmt::Display for BadLex {
@@ -155,25 +278,130 @@ impl fmt::Display for BadLex {
 	match self {
 	    NonTokenChar(c) => write!(f,"No valid token includes {}.",c),
 	    ExpectedChar(c1,c2) => write!(f,"After {} expected {}.",c1,c2),
+	    UnterminatedComment => write!(f,"Unterminated block comment."),
 	    Internal(err) => write!(f,"Implemtation error: {}.",err)
 	}
     }
 }
 
+impl std::error::Error for BadLex {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+	match self {
+	    BadLex::Internal(err) => Some(err),
+	    _ => None
+	}
+    }
+}
+
+impl BadLex {
+    /// Diagnostic for a lexical error at `index`.
+    pub fn to_diagnostic (&self, index : ByteIndex) -> Diagnostic {
+	let label = match self {
+	    BadLex::UnterminatedComment => "comment starts here",
+	    _ => "lexical error"
+	};
+	let diagnostic = Diagnostic::error(self.to_string())
+	    .with_label(ByteSpan::from_offset(index, ByteOffset (1)), label);
+	match self {
+	    BadLex::Internal(_) if cfg!(not(feature = "bigint")) =>
+		diagnostic.with_note(
+		    "no integer type is wider than i64 unless built with the bigint feature"),
+	    _ => diagnostic
+	}
+    }
+}
+
 pub struct Lexer<'input> {
-    chars : Peekable<CharIndices<'input>> // characters to lex.
+    chars : Peekable<CharIndices<'input>>, // characters to lex.
+    start : ByteIndex, // index of the file in its codemap.
+    comments : bool, // emit comments as tokens.
+    operand : bool // last token ends an operand.
 }
 
 impl<'input> Lexer<'input> {
     pub fn new(source: &'input FileMap) -> Self {
-        Lexer { chars: source.src().char_indices().peekable() }
+        Lexer {
+	    chars: source.src().char_indices().peekable(),
+	    start: source.span().start(),
+	    comments: false,
+	    operand: false
+	}
+    }
+
+    /// A lexer that emits comments as `COMMENT` tokens
+    /// instead of skipping them.
+    pub fn with_comments(source: &'input FileMap) -> Self {
+	Lexer { comments: true, ..Lexer::new(source) }
+    }
+
+    /// Lexes `token`, whose character after `c` must be `next`.
+    fn expect (&mut self, start : ByteIndex, c : char, next : char, token : Token)
+	       -> Result<Spanned,(ByteIndex,BadLex)> {
+	match self.chars.next() {
+	    Some ((_,ch)) if ch == next => Ok (spanned (start,token)),
+	    _ => Err ((start, BadLex::ExpectedChar (c,next)))
+	}
+    }
+
+    /// Lexes a numeral whose first character is `c`.
+    fn number (&mut self, start : ByteIndex, c : char)
+	       -> Result<Spanned,(ByteIndex,BadLex)> {
+	let mut num = c.to_string();
+	self.chars
+	    .by_ref()
+	    .peeking_take_while(|ch| ch.1.is_ascii_digit())
+	    .for_each(|ch| num.push(ch.1));
+	let end = start + ByteOffset (num.len() as i64);
+	num.parse::<Integer>()
+	    .map_err
+	    (|err| (start,
+		    BadLex::Internal (err)))
+	    .map (|z| (start, Token::NUM(z), end))
+    }
+
+    /// Consumes the rest of a line comment whose first `/` was `c`.
+    fn line_comment (&mut self, c : char) -> String {
+	let mut text = c.to_string();
+	self.chars
+	    .by_ref()
+	    .peeking_take_while(|ch| ch.1 != '\n')
+	    .for_each(|ch| text.push(ch.1));
+	text
+    }
+
+    /// Consumes the rest of a block comment whose first `/` was `c`.
+    /// Block comments nest; `None` if the file ends inside one.
+    fn block_comment (&mut self, c : char) -> Option<String> {
+	let mut text = c.to_string();
+	text.push(self.chars.next()?.1);
+	let mut depth = 1;
+	while let Some ((_,ch)) = self.chars.next() {
+	    text.push(ch);
+	    match (ch, self.chars.peek().map(|p| p.1)) {
+		('/', Some ('*')) => {
+		    text.push(self.chars.next()?.1);
+		    depth += 1
+		}
+		('*', Some ('/')) => {
+		    text.push(self.chars.next()?.1);
+		    depth -= 1;
+		    if depth == 0 { return Some (text) }
+		}
+		_ => ()
+	    }
+	}
+	None
+    }
+
+    /// Index in the codemap of the `i`th byte of the file.
+    fn index (&self, i : usize) -> ByteIndex {
+	self.start + ByteOffset (i as i64)
     }
 }
 
-pub fn spanned (i : usize, token : Token) -> Spanned {
+pub fn spanned (start : ByteIndex, token : Token) -> Spanned {
     let offset = token.len() as i64;
-    (ByteIndex(i as u32), token,
-     ByteIndex(i as u32) + ByteOffset (offset))
+    (start, token, start + ByteOffset (offset))
 }
 
 impl<'a> Iterator for Lexer<'a> {
@@ -185,81 +413,112 @@ impl<'a> Iterator for Lexer<'a> {
 
 		// Skip whitespace.
 		if c.is_whitespace() { continue }
-		
-		return Some (match c {
-		    '{' => Ok (spanned (i,Token::LBRACE)),
-		    '}' => Ok (spanned (i,Token::RBRACE)),
-		    '(' => Ok (spanned (i,Token::LPAREN)),
-		    ')' => Ok (spanned (i,Token::RPAREN)),
-		    ';' => Ok (spanned (i,Token::SEMICOLON)),
-		    '+' => Ok (spanned (i,Token::ADD)),
-		    '*' => Ok (spanned (i,Token::MUL)),
-		    '-' => Ok (spanned (i,Token::SUB)),
-		    '!' => Ok (spanned (i,Token::NOT)),
-		    ':' => {
-			if let Some((_,'=')) = self.chars.next() {
-			    Ok (spanned (i,Token::ASGN))
-			} else {
-			    Err ((ByteIndex(i as u32),
-				  BadLex::ExpectedChar (':','='))) }
+
+		let start = self.index(i);
+
+		// Skip comments, or emit them as tokens.
+		let comment = match (c, self.chars.peek().map(|p| p.1)) {
+		    ('/', Some ('/')) => Some (self.line_comment(c)),
+		    ('/', Some ('*')) => match self.block_comment(c) {
+			Some (text) => Some (text),
+			None => return Some (Err ((start,
+						   BadLex::UnterminatedComment)))
 		    },
-		    '<' => {
-			if let Some((_,'?')) = self.chars.next() {
-			    Ok (spanned (i,Token::LT))
-			} else {
-			    Err ((ByteIndex(i as u32),
-				  BadLex::ExpectedChar ('<','?'))) }
+		    _ => None
+		};
+		if let Some (text) = comment {
+		    if self.comments {
+			return Some (Ok (spanned (start, Token::COMMENT(text))))
+		    }
+		    continue
+		}
+
+		let result = match c {
+		    '{' => Ok (spanned (start,Token::LBRACE)),
+		    '}' => Ok (spanned (start,Token::RBRACE)),
+		    '(' => Ok (spanned (start,Token::LPAREN)),
+		    '[' => Ok (spanned (start,Token::LBRACKET)),
+		    ']' => Ok (spanned (start,Token::RBRACKET)),
+		    ')' => Ok (spanned (start,Token::RPAREN)),
+		    ';' => Ok (spanned (start,Token::SEMICOLON)),
+		    ',' => Ok (spanned (start,Token::COMMA)),
+		    '+' => Ok (spanned (start,Token::ADD)),
+		    '*' => Ok (spanned (start,Token::MUL)),
+		    '/' => Ok (spanned (start,Token::DIV)),
+		    '%' => Ok (spanned (start,Token::MOD)),
+		    // A minus sign right before a digit is part of
+		    // a negative numeral, unless it follows an operand,
+		    // in which case it is a subtraction.
+		    '-' => match self.chars.peek().map(|p| p.1) {
+			Some (d) if d.is_ascii_digit() && !self.operand
+			    => self.number(start, c),
+			_ => Ok (spanned (start,Token::SUB))
 		    },
-		    '=' => {
-			if let Some((_,'?')) = self.chars.next() {
-			    Ok (spanned (i,Token::EQ))
-			} else {
-			    Err ((ByteIndex(i as u32),
-				  BadLex::ExpectedChar ('=','?'))) }
+		    '!' => match self.chars.peek().map(|p| p.1) {
+			Some ('=') => {
+			    self.chars.next();
+			    self.expect(start, '=', '?', Token::NE)
+			}
+			_ => Ok (spanned (start,Token::NOT))
+		    },
+		    ':' => match self.chars.peek().map(|p| p.1) {
+			Some ('=') => {
+			    self.chars.next();
+			    Ok (spanned (start,Token::ASGN))
+			}
+			_ => Ok (spanned (start,Token::COLON))
+		    },
+		    '<' => match self.chars.peek().map(|p| p.1) {
+			Some ('=') => {
+			    self.chars.next();
+			    self.expect(start, '=', '?', Token::LE)
+			}
+			_ => self.expect(start, '<', '?', Token::LT)
 		    },
+		    '>' => match self.chars.peek().map(|p| p.1) {
+			Some ('=') => {
+			    self.chars.next();
+			    self.expect(start, '=', '?', Token::GE)
+			}
+			_ => self.expect(start, '>', '?', Token::GT)
+		    },
+		    '=' => self.expect(start, '=', '?', Token::EQ),
 		    _ => {
 			if c.is_ascii_digit() {
-			    let mut num = c.to_string();
-			    self.chars
-				.by_ref()
-				.peeking_take_while(|ch| ch.1.is_ascii_digit())
-				.for_each(|ch| num.push(ch.1));
-			    num.parse::<i32>()
-				.map_err
-				(|err| (ByteIndex (i as u32),
-					BadLex::Internal (err)))
-				.map (|z| spanned (i, Token::NUM(z)))
+			    self.number(start, c)
 			} else if c.is_alphabetic() {
 			    let mut s = c.to_string();
 			    self.chars
 				.by_ref()
 				.peeking_take_while(|ch| ch.1.is_alphanumeric())
 				.for_each(|ch| s.push(ch.1));
-			    Ok (spanned (i, token_of_string(&s)))
+			    Ok (spanned (start, token_of_string(&s)))
 			} else {
-			    Err ((ByteIndex(i as u32),
+			    Err ((start,
 				  BadLex::NonTokenChar (c)))
 			}
 		    }
-		})
+		};
+		self.operand = matches!(
+		    result,
+		    Ok ((_, Token::NUM(_), _)) |
+		    Ok ((_, Token::VAR(_), _)) |
+		    Ok ((_, Token::RPAREN, _)) |
+		    Ok ((_, Token::RBRACKET, _)));
+		return Some (result)
 	    } else { return None }
 	}
     }
 }
 
 pub fn tokenize(src : &Arc<FileMap>)
-	    -> Result<Vec::<Spanned>, (u32, u32, BadLex)> {
+	    -> Result<Vec::<Spanned>, Diagnostic> {
     let mut tokens = Vec::new();
     let mut lexer = Lexer::new(&src);
     while let Some (result) = lexer.next() {
 	match result {
 	    Ok (sp) => tokens.push(sp),
-	    Err ((index, err)) => {
-		let (LineIndex (row), ColumnIndex (col))
-		    = src.location(index)
-		    .expect("Looking up bad index in file");
-		return Err ((row, col, err))
-	    }
+	    Err ((index, err)) => return Err (err.to_diagnostic(index))
 	}
     }
     Ok (tokens)
@@ -7,13 +7,27 @@ extern crate codespan;
 extern crate clap;
 extern crate lalrpop_util;
 extern crate peeking_take_while;
+#[cfg(feature = "bigint")]
+extern crate num_bigint;
 
+pub mod assigned;
+pub mod context;
+pub mod diagnostic;
 pub mod error;
 pub mod eval;
 pub mod fold;
+pub mod input;
+pub mod integer;
 pub mod lexer;
+pub mod limits;
 pub mod op;
+pub mod output;
 pub mod parser;
+pub mod resolve;
+pub mod run;
 pub mod step;
 pub mod store;
 pub mod syntax;
+pub mod types;
+
+pub use run::{run_path, run_source, Engine, Options, Outcome};
@@ -0,0 +1,167 @@
+use std::{fmt, io, panic, thread, time::{Duration, Instant}};
+use crate::{error::Error, store::Store, syntax::Loc};
+
+// Resource limits for running untrusted programs.
+// `None` means unlimited, except for the call depth,
+// which defaults to `DEPTH`.
+// Each nested call takes Rust stack in either engine,
+// so `run` and the command line run programs on a thread
+// with a stack sized from the call depth limit,
+// and too deep a recursion stops with an error, not an overflow.
+// Code that drives an engine itself can do the same with `Limits::on_stack`.
+#[derive(Clone, Debug, Default)]
+pub struct Limits {
+    pub steps: Option<u64>,      // evaluation steps.
+    pub store: Option<usize>,    // integers in the store, array elements included.
+    pub output: Option<usize>,   // printed lines.
+    pub time: Option<Duration>,  // wall-clock time.
+    pub depth: Option<usize>,    // nested procedure calls.
+}
+
+pub const DEPTH: usize = 100;
+
+// Rust stack a nested call may take, with room for
+// the expressions around it: a plain recursive call
+// takes about 16KB in a debug build and 1KB in a release build.
+const FRAME: usize = if cfg!(debug_assertions) { 64 * 1024 } else { 16 * 1024 };
+
+// Rust stack for the rest of a run.
+const BASE: usize = 8 * 1024 * 1024;
+
+impl Limits {
+    /// The call depth limit.
+    pub fn depth(&self) -> usize {
+	self.depth.unwrap_or(DEPTH)
+    }
+
+    /// Runs `f` on a thread with enough stack for as many nested calls
+    /// as the call depth limit allows.
+    /// Fails if there is no memory for the stack.
+    pub fn on_stack<T: Send>(&self, f: impl FnOnce() -> T + Send) -> io::Result<T> {
+	let size = self.depth().saturating_mul(FRAME).saturating_add(BASE);
+	thread::scope(|scope| {
+	    let run = thread::Builder::new().stack_size(size).spawn_scoped(scope, f)?;
+	    Ok (run.join().unwrap_or_else(|err| panic::resume_unwind(err)))
+	})
+    }
+}
+
+// The limit a run exceeded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Limit {
+    Steps(u64),
+    Store(usize),
+    Output(usize),
+    Time(Duration),
+    Depth(usize),
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	match self {
+	    Limit::Steps(n)  => write!(f, "step limit of {}", n),
+	    Limit::Store(n)  => write!(f, "store limit of {} values", n),
+	    Limit::Output(n) => write!(f, "output limit of {} lines", n),
+	    Limit::Time(d)   => write!(f, "time limit of {}ms", d.as_millis()),
+	    Limit::Depth(n)  => write!(f, "call depth limit of {}", n)
+	}
+    }
+}
+
+/// The state of a run when a limit stopped it.
+#[derive(Clone, Debug)]
+pub struct Partial {
+    pub steps: u64,
+    pub printed: usize,
+    pub store: Store,
+}
+
+/// Resources used so far by one run, checked against its limits.
+/// Each engine counts one step per reduction it performs.
+#[derive(Debug)]
+pub struct Meter {
+    limits: Limits,
+    deadline: Option<Instant>,
+    pub steps: u64,
+    pub printed: usize,
+    pub depth: usize,
+}
+
+impl Meter {
+    /// Starts the clock on a run.
+    pub fn new(limits: Limits) -> Self {
+	let deadline = limits.time.map(|d| Instant::now() + d);
+	Meter { limits, deadline, steps: 0, printed: 0, depth: 0 }
+    }
+
+    pub fn unlimited() -> Self {
+	Meter::new(Limits::default())
+    }
+
+    fn exceeded(&self, limit: Limit, s: &Store, l: Loc) -> Error {
+	Error::LimitExceeded(
+	    limit,
+	    Box::new(Partial {
+		steps: self.steps,
+		printed: self.printed,
+		store: s.clone()
+	    }),
+	    l)
+    }
+
+    /// Counts a step at `l`, and checks the step, store and time limits.
+    pub fn step(&mut self, s: &Store, l: Loc) -> Result<(), Error> {
+	if let Some(n) = self.limits.steps {
+	    if self.steps >= n {
+		return Err (self.exceeded(Limit::Steps(n), s, l))
+	    }
+	}
+	if let Some(n) = self.limits.store {
+	    if s.len() > n {
+		return Err (self.exceeded(Limit::Store(n), s, l))
+	    }
+	}
+	if let (Some(deadline), Some(d)) = (self.deadline, self.limits.time) {
+	    if Instant::now() >= deadline {
+		return Err (self.exceeded(Limit::Time(d), s, l))
+	    }
+	}
+	self.steps += 1;
+	Ok (())
+    }
+
+    /// Counts a printed line at `l`, and checks the output limit.
+    pub fn print(&mut self, s: &Store, l: Loc) -> Result<(), Error> {
+	if let Some(n) = self.limits.output {
+	    if self.printed >= n {
+		return Err (self.exceeded(Limit::Output(n), s, l))
+	    }
+	}
+	self.printed += 1;
+	Ok (())
+    }
+
+    /// Checks the store limit before allocating `n` more integers at `l`.
+    pub fn alloc(&self, s: &Store, n: usize, l: Loc) -> Result<(), Error> {
+	match self.limits.store {
+	    Some (m) if s.len().saturating_add(n) > m =>
+		Err (self.exceeded(Limit::Store(m), s, l)),
+	    _ => Ok (())
+	}
+    }
+
+    /// Enters a procedure called at `l`, and checks the call depth.
+    pub fn call(&mut self, s: &Store, l: Loc) -> Result<(), Error> {
+	let n = self.limits.depth();
+	if self.depth >= n {
+	    return Err (self.exceeded(Limit::Depth(n), s, l))
+	}
+	self.depth += 1;
+	Ok (())
+    }
+
+    /// Leaves a procedure.
+    pub fn ret(&mut self) {
+	self.depth -= 1
+    }
+}
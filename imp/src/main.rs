@@ -1,5 +1,5 @@
-use std::path::PathBuf;
-use imp::{lexer,parser,store,syntax};
+use std::{path::PathBuf, process::ExitCode, time::Duration};
+use imp::{context::Context,diagnostic::Diagnostic,input::{self,Input},limits::{Limits,Meter},op::{Logic,Overflow},output,run,store,syntax};
 use codespan::CodeMap;
 use clap::Parser;
 
@@ -8,52 +8,108 @@ use clap::Parser;
 struct Args {
     #[clap(short, long)]
     fold: bool, // constant fold
-    
+
     #[clap(short, long)]
     eval: bool, // evaluate
-    
+
     #[clap(short, long)]
     step: bool, // small-step
-    
+
+    #[clap(long, default_value = "trap")]
+    overflow: Overflow, // trap, wrap or saturate
+
+    #[clap(long, default_value = "short")]
+    logic: Logic, // short-circuit or strict `and` and `or`
+
+    #[clap(long)]
+    max_steps: Option<u64>, // evaluation steps
+
+    #[clap(long)]
+    max_store: Option<usize>, // stored values, array elements included
+
+    #[clap(long)]
+    max_output: Option<usize>, // printed lines
+
+    #[clap(long)]
+    max_time_ms: Option<u64>, // wall-clock milliseconds
+
+    #[clap(long)]
+    max_depth: Option<usize>, // nested procedure calls
+
+    #[clap(long)]
+    input: Option<input::Queue>, // values to read, e.g. 1,2,3; stdin if absent
+
     #[clap(parse(from_os_str))]
     path: PathBuf, // file path
 }
 
-fn main() -> Result<(),()> {
-    // Parse command-line arguments.
-    let args : Args = Args::parse();
-
+fn run(args : Args, codemap : &mut CodeMap) -> Result<(),Vec<Diagnostic>> {
     // Create filemap.
-    let mut codemap = CodeMap::new();
-    let file = codemap.add_filemap_from_disk(args.path)
-	.map_err(|err| println!("File error: {}",err))?;
-
-    // Lex.
-    let tokens = lexer::tokenize(&file)
+    let file = codemap.add_filemap_from_disk(&args.path)
 	.map_err(|err|
-		 println!(
-		     "Lexical error at line {} column {}: {}",
-		     err.0,err.1,err.2))?;
+		 Diagnostic::error(
+		     format!("Could not read {}: {}", args.path.display(), err)))?;
+
+    // Lex, parse and check.
+    let mut ast: syntax::Program = run::parse(&file)?;
+    for warning in run::check(&mut ast)? {
+	warning.emit(codemap)
+    }
 
-    // Parse.
-    let mut ast: syntax::Cmd = parser::parse(&file,tokens)?;
-    
     println!("------------ Program parsed as: ------------");
     println!("{}",ast);
     if args.fold {
 	println!("------------ Constant-folded program: ------------");
-	let ast = ast.fold();
+	let ast = ast.fold(args.overflow, args.logic);
 	println!("{}",ast);
+	for warning in ast.refuted() {
+	    warning.emit(codemap)
+	}
     }
+    let mut meter = Meter::new(Limits {
+	steps: args.max_steps,
+	store: args.max_store,
+	output: args.max_output,
+	time: args.max_time_ms.map(Duration::from_millis),
+	depth: args.max_depth,
+    });
+    let mut inp : Box<dyn Input> = match args.input {
+	Some (queue) => Box::new(queue),
+	None => Box::new(input::Stdin::default())
+    };
+    let mut stdout = output::Stdout::default();
     if args.step {
 	println!("------------ Stepping program ------------");
-	return ast.normalize(&mut store::Store::new())
-	    .map_err(|err| println!("Evaluation error: {}", err));
+	let cx = Context::new(args.overflow, args.logic, &ast.procs, &mut meter, &mut stdout, &mut *inp);
+	return Ok (ast.main.normalize(&mut store::Store::new(), cx)?);
     }
     if args.eval {
 	println!("------------ Executing program ------------");
-	return ast.eval(&mut store::Store::new())
-	    .map_err(|err| println!("Evaluation error: {}", err));
+	let cx = Context::new(args.overflow, args.logic, &ast.procs, &mut meter, &mut stdout, &mut *inp);
+	return Ok (ast.main.eval(&mut store::Store::new(), cx)?);
     }
     Ok (())
 }
+
+fn main() -> ExitCode {
+    // Parse command-line arguments.
+    let args : Args = Args::parse();
+
+    let mut codemap = CodeMap::new();
+    let limits = Limits { depth: args.max_depth, ..Limits::default() };
+    let result = limits.on_stack(|| run(args, &mut codemap)).unwrap_or_else(|err|
+	Err (vec![Diagnostic::error(
+	    format!("Could not make a stack for a call depth limit of {}: {}", limits.depth(), err))
+		  .with_note("a lower limit needs less stack")]));
+    match result {
+	Ok (()) => ExitCode::SUCCESS,
+	Err (diagnostics) => {
+	    let failed = diagnostics.iter().any(Diagnostic::is_error);
+	    for d in diagnostics {
+		d.emit(&codemap)
+	    }
+	    // An infeasible path ends the run without failing it.
+	    if failed { ExitCode::FAILURE } else { ExitCode::SUCCESS }
+	}
+    }
+}
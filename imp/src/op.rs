@@ -1,27 +1,152 @@
-use crate::syntax::{Aop, Cop, Bop};
+use crate::{error::Error, integer::Integer, syntax::{Aop, Cop, Bop, Loc}, types::Width};
+use std::{fmt, str::FromStr};
+
+// What integer arithmetic does when a result
+// does not fit in the width of its type.
+// `int` has no fixed width with the `bigint` feature,
+// so its results always fit and the policy has no effect.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Overflow {
+    #[default]
+    Trap,     // raise an error.
+    Wrap,     // two's complement wrap-around.
+    Saturate, // clamp to the smallest or largest value of the type.
+}
+
+impl fmt::Display for Overflow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	match self {
+	    Overflow::Trap     => write!(f, "trap"),
+	    Overflow::Wrap     => write!(f, "wrap"),
+	    Overflow::Saturate => write!(f, "saturate")
+	}
+    }
+}
+
+impl FromStr for Overflow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+	match s {
+	    "trap"     => Ok (Overflow::Trap),
+	    "wrap"     => Ok (Overflow::Wrap),
+	    "saturate" => Ok (Overflow::Saturate),
+	    _ => Err (format!(
+		"unknown overflow policy {}, expected trap, wrap or saturate", s))
+	}
+    }
+}
+
+// How `and` and `or` evaluate their operands.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Logic {
+    #[default]
+    Short,  // the right operand only if the left does not decide the result.
+    Strict, // both operands, left to right.
+}
+
+impl fmt::Display for Logic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	match self {
+	    Logic::Short  => write!(f, "short"),
+	    Logic::Strict => write!(f, "strict")
+	}
+    }
+}
+
+impl FromStr for Logic {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+	match s {
+	    "short"  => Ok (Logic::Short),
+	    "strict" => Ok (Logic::Strict),
+	    _ => Err (format!("unknown logic {}, expected short or strict", s))
+	}
+    }
+}
+
+/// Negates `z` at width `w` under overflow policy `ov`,
+/// blaming `l` for any error.
+pub fn negate(z: &Integer, w: Width, ov: Overflow, l: Loc) -> Result<Integer, Error> {
+    let n = match ov {
+	Overflow::Trap => z.checked_neg(),
+	Overflow::Wrap => Some (z.wrapping_neg()),
+	Overflow::Saturate => Some (z.saturating_neg())
+    };
+    n.and_then(|n| w.fit(n, ov))
+	.ok_or_else(|| Error::NegationOverflow(w, z.clone(), l))
+}
 
 impl Aop {
-    pub fn eval(&self, z1: i32, z2: i32) -> i32 {
+    /// Whether the operator fails when its right operand is zero.
+    pub fn divides(&self) -> bool {
+	matches!(self, Aop::Div | Aop::Mod)
+    }
+
+    /// Applies the operator at width `w` under overflow policy `ov`,
+    /// blaming `l` for any error.
+    /// The result is computed in full first, then narrowed to `w`,
+    /// which gives the same result as computing at `w` directly.
+    pub fn eval(&self, z1: &Integer, z2: &Integer, w: Width, ov: Overflow, l: Loc)
+		-> Result<Integer, Error> {
 	use Aop::*;
-        match self {
-            Add => z1 + z2,
-            Sub => z1 - z2,
-            Mul => z1 * z2,
-        }
+	if self.divides() && z2.is_zero() {
+	    return Err (Error::DivisionByZero(*self, z1.clone(), l))
+	}
+        let z = match ov {
+	    Overflow::Trap => match self {
+		Add => z1.checked_add(z2),
+		Sub => z1.checked_sub(z2),
+		Mul => z1.checked_mul(z2),
+		Div => z1.checked_div(z2),
+		Mod => Some (z1.rem(z2)),
+	    },
+	    Overflow::Wrap => Some (match self {
+		Add => z1.wrapping_add(z2),
+		Sub => z1.wrapping_sub(z2),
+		Mul => z1.wrapping_mul(z2),
+		Div => z1.wrapping_div(z2),
+		Mod => z1.rem(z2),
+	    }),
+	    Overflow::Saturate => Some (match self {
+		Add => z1.saturating_add(z2),
+		Sub => z1.saturating_sub(z2),
+		Mul => z1.saturating_mul(z2),
+		Div => z1.saturating_div(z2),
+		Mod => z1.rem(z2),
+	    })
+        };
+	z.and_then(|z| w.fit(z, ov))
+	    .ok_or_else(|| Error::Overflow(*self, w, z1.clone(), z2.clone(), l))
     }
 }
 
 impl Cop {
-    pub fn eval(&self, z1: i32, z2: i32) -> bool {
+    pub fn eval(&self, z1: &Integer, z2: &Integer) -> bool {
 	use Cop::*;
         match self {
             Eq => z1 == z2,
+            Ne => z1 != z2,
             Lt => z1 < z2,
+            Le => z1 <= z2,
+            Gt => z1 > z2,
+            Ge => z1 >= z2,
         }
     }
 }
 
 impl Bop {
+    /// The result, if the left operand `b1` decides it
+    /// and `logic` lets it skip the right operand.
+    pub fn short(&self, b1: bool, logic: Logic) -> Option<bool> {
+	match (logic, self, b1) {
+	    (Logic::Short, Bop::And, false) => Some (false),
+	    (Logic::Short, Bop::Or, true) => Some (true),
+	    _ => None
+	}
+    }
+
     pub fn eval(&self, b1: bool, b2: bool) -> bool {
 	use Bop::*;
         match self {
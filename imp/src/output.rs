@@ -0,0 +1,59 @@
+use std::io::{self, Write};
+use crate::store::Value;
+
+// Where `print` sends its values.
+pub trait Output {
+    fn print(&mut self, v: &Value) -> io::Result<()>;
+}
+
+/// Prints each value on its own line of stdout, after a prefix.
+pub struct Stdout {
+    pub prefix: String,
+}
+
+impl Stdout {
+    pub fn new(prefix: &str) -> Self {
+	Stdout { prefix: String::from(prefix) }
+    }
+}
+
+/// The CLI format, `OUTPUT: 42`.
+impl Default for Stdout {
+    fn default() -> Self {
+	Stdout::new("OUTPUT: ")
+    }
+}
+
+impl Output for Stdout {
+    fn print(&mut self, v: &Value) -> io::Result<()> {
+	writeln!(io::stdout().lock(), "{}{}", self.prefix, v)
+    }
+}
+
+/// Collects the printed values in memory.
+#[derive(Clone, Debug, Default)]
+pub struct Collect(pub Vec<Value>);
+
+impl Output for Collect {
+    fn print(&mut self, v: &Value) -> io::Result<()> {
+	self.0.push(v.clone());
+	Ok (())
+    }
+}
+
+/// Writes each value on its own line to any writer.
+pub struct Writer<W: Write>(pub W);
+
+impl<W: Write> Output for Writer<W> {
+    fn print(&mut self, v: &Value) -> io::Result<()> {
+	writeln!(self.0, "{}", v)
+    }
+}
+
+/// Any closure taking the printed value.
+impl<F: FnMut(&Value)> Output for F {
+    fn print(&mut self, v: &Value) -> io::Result<()> {
+	self(v);
+	Ok (())
+    }
+}
@@ -1,56 +1,104 @@
-use crate::{syntax,lexer::{Token,Spanned,BadLex}};
-use std::sync::Arc;
-use codespan::{FileMap, LineIndex, ColumnIndex, ByteIndex};
+use crate::{syntax::{self,Aexpr,Bexpr,Cmd,Expr,Loc},diagnostic::Diagnostic,lexer::{Token,Spanned},types::Type};
+use codespan::{ByteIndex, ByteOffset, ByteSpan, FileMap};
 use lalrpop_util::{lalrpop_mod, ParseError};
 lalrpop_mod!(pub grammar);
 
-fn handle_parse_error<'a>
-    (src : &'a Arc<FileMap>)
-     -> impl FnOnce(ParseError<ByteIndex,Token,BadLex>) -> () + 'a {
-	move |err : ParseError<ByteIndex,Token,BadLex>| {
-	    println!("Parsing error:");
-	    match err {
-		ParseError::InvalidToken {location:index} => {
-		    let (LineIndex(row), ColumnIndex(col))
-			= src.location(index)
-			.expect("Looking up bad index in file for invalid token.");
-		    println!("Invalid token at line {} column {}.",row,col);
-		}
-		ParseError::UnrecognizedEOF {location:index, expected:exp} => {
-		    let (LineIndex(row), ColumnIndex(col))
-			= src.location(index)
-			.expect("Looking up bad index in file for unrecognized eof.");
-		    println!(
-			"Unrecognized end of file at line {} column {}, expected {:?}.",
-			row,col,exp);
-		}
-		ParseError::UnrecognizedToken {token:(i1,tk,i2), expected:exp} => {
-		    let (LineIndex(r1), ColumnIndex(c1))
-			= src.location(i1)
-			.expect("Looking up bad index in file for unrecognized token's start.");
-		    let (LineIndex(r2), ColumnIndex(c2))
-			= src.location(i2)
-			.expect("Looking up bad index in file for unrecognized token's start.");
-		    println!(
-			"Unrecognized token {} at line {} column {} to line {} column {}, expected {:?}.",
-			tk,r1,c1,r2,c2,exp);
-		}
-		ParseError::ExtraToken {token:(i1,tk,i2)} => {
-		    let (LineIndex(r1), ColumnIndex(c1))
-			= src.location(i1)
-			.expect("Looking up bad index in file for extra token's end.");
-		    let (LineIndex(r2), ColumnIndex(c2))
-			= src.location(i2)
-			.expect("Looking up bad index in file for extra token's end.");
-		    println!(
-			"Extra token {} at line {} column {} to line {} column {}.",
-			tk,r1,c1,r2,c2);
-		}
-		ParseError::User {error:err} => println!("Lexical error: {}",err)
-	    }
+/// An error found while building the syntax tree.
+#[derive(Clone, Debug)]
+pub enum SyntaxError {
+    Expected(&'static str, Loc), // an expression of the other kind
+    UnknownType(String, Loc),
+    Arity(usize, usize, Loc),         // of a parallel assignment
+    DuplicateTarget(String, Loc, Loc), // and where it was first assigned
+}
+
+type Build<T> = Result<T, ParseError<ByteIndex,Token,SyntaxError>>;
+
+// Integer and boolean expressions share one grammar.
+// These take an operand where only one kind may appear;
+// a variable on its own may be of either.
+
+pub fn aexpr (e : Expr) -> Build<Box<Aexpr>> {
+    match e {
+	Expr::A(e) => Ok (Box::new(e)),
+	Expr::B(Bexpr::Var(x,l)) => Ok (Box::new(Aexpr::Var(x,l))),
+	Expr::B(e) => Err (ParseError::User {error: SyntaxError::Expected("an integer", e.loc())})
+    }
+}
+
+pub fn aexprs (es : Vec<Expr>) -> Build<Vec<Aexpr>> {
+    es.into_iter().map(|e| aexpr(e).map(|e| *e)).collect()
+}
+
+pub fn bexpr (e : Expr) -> Build<Box<Bexpr>> {
+    match e {
+	Expr::B(e) => Ok (Box::new(e)),
+	Expr::A(Aexpr::Var(x,l)) => Ok (Box::new(Bexpr::Var(x,l))),
+	Expr::A(e) => Err (ParseError::User {error: SyntaxError::Expected("a boolean", e.loc())})
+    }
+}
+
+pub fn ty (t : String, l : Loc) -> Build<Type> {
+    t.parse().map_err(|_| ParseError::User {error: SyntaxError::UnknownType(t, l)})
+}
+
+// The source text between `l` and `r`, as written.
+pub fn text (file : &FileMap, l : ByteIndex, r : ByteIndex) -> String {
+    String::from(file.src_slice(ByteSpan::new(l, r)).expect("span of a parsed token in its file"))
+}
+
+// `x1, ..., xn := e1, ..., en`, with as many values as distinct targets.
+pub fn parallel (xs : Vec<(String,Loc)>, es : Vec<Expr>, l : Loc) -> Build<Cmd> {
+    if xs.len() != es.len() {
+	return Err (ParseError::User {error: SyntaxError::Arity(xs.len(), es.len(), l)})
+    }
+    for (i, (x, lx)) in xs.iter().enumerate() {
+	if let Some ((_, first)) = xs[..i].iter().find(|(y, _)| y == x) {
+	    return Err (ParseError::User {
+		error: SyntaxError::DuplicateTarget(x.clone(), *lx, *first)})
 	}
     }
+    Ok (Cmd::ParAss(xs.into_iter().map(|(x, _)| x).collect(), es, l))
+}
+
+fn expected_note (expected : &[String]) -> String {
+    format!("expected one of {}", expected.join(", "))
+}
+
+fn parse_error_diagnostic (err : ParseError<ByteIndex,Token,SyntaxError>) -> Diagnostic {
+    match err {
+	ParseError::InvalidToken {location:index} =>
+	    Diagnostic::error("Invalid token.")
+	    .with_label(ByteSpan::from_offset(index, ByteOffset (1)), ""),
+	ParseError::UnrecognizedEOF {location:index, expected:exp} =>
+	    Diagnostic::error("Unrecognized end of file.")
+	    .with_label(ByteSpan::new(index, index), "file ends here")
+	    .with_note(expected_note(&exp)),
+	ParseError::UnrecognizedToken {token:(i1,tk,i2), expected:exp} =>
+	    Diagnostic::error(format!("Unrecognized token {}.",tk))
+	    .with_label(ByteSpan::new(i1, i2), "unexpected token")
+	    .with_note(expected_note(&exp)),
+	ParseError::ExtraToken {token:(i1,tk,i2)} =>
+	    Diagnostic::error(format!("Extra token {}.",tk))
+	    .with_label(ByteSpan::new(i1, i2), "extra token"),
+	ParseError::User {error:SyntaxError::Expected(kind,l)} =>
+	    Diagnostic::error(format!("Expected {} expression.",kind))
+	    .with_label(l.0, format!("not {} expression",kind)),
+	ParseError::User {error:SyntaxError::UnknownType(t,l)} =>
+	    Diagnostic::error(format!("Unknown type {}.",t))
+	    .with_label(l.0, "not a type")
+	    .with_note("types are bool, int, i8, i16, i32, i64, u8, u16 and u32"),
+	ParseError::User {error:SyntaxError::Arity(n,m,l)} =>
+	    Diagnostic::error(format!("Assignment to {} variables needs {} values, found {}.",n,n,m))
+	    .with_label(l.0, "needs one value per variable"),
+	ParseError::User {error:SyntaxError::DuplicateTarget(x,l,first)} =>
+	    Diagnostic::error(format!("{} is assigned twice at once.",x))
+	    .with_label(l.0, "assigned again here")
+	    .with_secondary(first.0, "first assigned here")
+    }
+}
 
-pub fn parse (src : &Arc<FileMap>, tokens : Vec<Spanned>) -> Result<syntax::Cmd, ()> {
-    grammar::SeqParser::new().parse(tokens).map_err(handle_parse_error(src))
+/// Parses the tokens lexed from `file`.
+pub fn parse (file : &FileMap, tokens : Vec<Spanned>) -> Result<syntax::Program, Diagnostic> {
+    grammar::ProgramParser::new().parse(file, tokens).map_err(parse_error_diagnostic)
 }
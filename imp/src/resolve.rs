@@ -0,0 +1,189 @@
+use std::collections::{HashMap, HashSet};
+use crate::{diagnostic::Diagnostic, syntax::{Aexpr, Bexpr, Cmd, Expr, Loc, Proc, Program}};
+
+// Name resolution, checked before a program runs:
+// every call names a declared procedure with the right number of arguments,
+// `return` only appears in procedure bodies,
+// and `break` and `continue` only in loop bodies.
+// A procedure only assigns its parameters, its `let` variables
+// and the globals the main program assigns,
+// so each call keeps its temporaries to itself.
+
+struct Resolver<'a> {
+    procs: HashMap<&'a str, &'a Proc>,
+    globals: HashSet<String>,
+    locals: Vec<String>, // parameters and `let` variables in scope
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// The globals: variables the main program assigns
+/// outside the `let` blocks binding them.
+pub fn globals(main: &Cmd) -> HashSet<String> {
+    fn walk(c: &Cmd, locals: &mut Vec<String>, xs: &mut HashSet<String>) {
+	use Cmd::*;
+	let mut assign = |x: &String, locals: &Vec<String>| {
+	    if !locals.contains(x) { xs.insert(x.clone()); }
+	};
+	match c {
+	    Ass (x, _, _) | Alloc (x, _, _) | Read (x, _, _) => assign(x, locals),
+	    ParAss (ys, _, _) => for y in ys { assign(y, locals) },
+	    For (i, _, _, c, _) => { assign(i, locals); walk(c, locals, xs) }
+	    Seq (_, _, _) => for c in c.sequence() { walk(c, locals, xs) },
+	    If (_, c1, c2, _) | Loop (c1, c2, _) => { walk(c1, locals, xs); walk(c2, locals, xs) }
+	    While (_, c, _) | DoWhile (c, _, _) => walk(c, locals, xs),
+	    Let (x, _, c, _) => {
+		locals.push(x.clone());
+		walk(c, locals, xs);
+		locals.pop();
+	    }
+	    _ => ()
+	}
+    }
+    let mut xs = HashSet::new();
+    walk(main, &mut Vec::new(), &mut xs);
+    xs
+}
+
+impl<'a> Resolver<'a> {
+    fn aexpr(&mut self, e: &Aexpr) {
+	use Aexpr::*;
+	match e {
+	    Int (_, _) | Var (_, _) | Len (_, _) => (),
+	    Neg (_, e, _) | Index (_, e, _) => self.aexpr(e),
+	    Op (_, _, e1, e2, _) => { self.aexpr(e1); self.aexpr(e2) }
+	    Cond (b, e1, e2, _) => { self.bexpr(b); self.aexpr(e1); self.aexpr(e2) }
+	    Call (p, es, l) => {
+		match self.procs.get(p.as_str()) {
+		    None => self.diagnostics.push(
+			Diagnostic::error(format!("Unknown procedure {}", p))
+			    .with_label(l.0, "not declared")),
+		    Some (proc) if proc.params.len() != es.len() => self.diagnostics.push(
+			Diagnostic::error(
+			    format!("Procedure {} expects {} arguments, found {}",
+				    p, proc.params.len(), es.len()))
+			    .with_label(l.0, "wrong number of arguments")
+			    .with_secondary(proc.loc.0, "declared here")),
+		    Some (_) => ()
+		}
+		for e in es { self.aexpr(e) }
+	    }
+	    Body (_, c, _) => self.cmd(c, true, false)
+	}
+    }
+
+    fn bexpr(&mut self, e: &Bexpr) {
+	use Bexpr::*;
+	match e {
+	    Bool (_, _) | Var (_, _) => (),
+	    Not (e, _) => self.bexpr(e),
+	    COp (_, e1, e2, _) => { self.aexpr(e1); self.aexpr(e2) }
+	    BOp (_, e1, e2, _) => { self.bexpr(e1); self.bexpr(e2) }
+	}
+    }
+
+    fn expr(&mut self, e: &Expr) {
+	match e {
+	    Expr::A(e) => self.aexpr(e),
+	    Expr::B(e) => self.bexpr(e)
+	}
+    }
+
+    // Checks an assignment to `x` at `l`.
+    fn assign(&mut self, x: &str, in_proc: bool, l: Loc) {
+	if in_proc && !self.locals.iter().any(|y| y == x) && !self.globals.contains(x) {
+	    self.diagnostics.push(
+		Diagnostic::error(
+		    format!("{} is assigned in a procedure, but is not a parameter, local or global", x))
+		    .with_label(l.0, "not a variable of this procedure")
+		    .with_note(format!("bind it with `let {} := ... in {{ ... }}`", x)))
+	}
+    }
+
+    fn cmd(&mut self, c: &Cmd, in_proc: bool, in_loop: bool) {
+	use Cmd::*;
+	match c {
+	    Skip (_) | Decl (_, _, _) => (),
+	    Read (x, _, l) => self.assign(x, in_proc, *l),
+	    Ass (x, e, l) => { self.expr(e); self.assign(x, in_proc, *l) }
+	    Print (e, _) => self.expr(e),
+	    ParAss (xs, es, l) => {
+		for e in es { self.expr(e) }
+		for x in xs { self.assign(x, in_proc, *l) }
+	    }
+	    Alloc (a, e, l) => { self.aexpr(e); self.assign(a, in_proc, *l) }
+	    AssIndex (_, e1, e2, _) => { self.aexpr(e1); self.aexpr(e2) }
+	    Seq (_, _, _) => for c in c.sequence() { self.cmd(c, in_proc, in_loop) },
+	    If (e, c1, c2, _) => {
+		self.bexpr(e);
+		self.cmd(c1, in_proc, in_loop);
+		self.cmd(c2, in_proc, in_loop)
+	    }
+	    While (e, c, _) | DoWhile (c, e, _) => { self.bexpr(e); self.cmd(c, in_proc, true) }
+	    For (i, e1, e2, c, l) => {
+		self.aexpr(e1);
+		self.aexpr(e2);
+		self.assign(i, in_proc, *l);
+		self.cmd(c, in_proc, true)
+	    }
+	    Loop (c1, c2, _) => {
+		self.cmd(c1, in_proc, true);
+		self.cmd(c2, in_proc, in_loop)
+	    }
+	    Break (l) | Continue (l) => {
+		if !in_loop {
+		    self.diagnostics.push(
+			Diagnostic::error(format!("{} outside of a loop", c))
+			    .with_label(l.0, "not in a loop body"))
+		}
+	    }
+	    Assert (e, _, _) | Assume (e, _, _) => self.bexpr(e),
+	    Let (x, e, c, _) => {
+		self.expr(e);
+		self.locals.push(x.clone());
+		self.cmd(c, in_proc, in_loop);
+		self.locals.pop();
+	    }
+	    Return (e, l) => {
+		if !in_proc {
+		    self.diagnostics.push(
+			Diagnostic::error("return outside of a procedure")
+			    .with_label(l.0, "not in a procedure body"))
+		}
+		self.aexpr(e)
+	    }
+	}
+    }
+}
+
+/// Checks the names in `p`, reporting every error found.
+pub fn check(p: &Program) -> Result<(), Vec<Diagnostic>> {
+    let mut r = Resolver {
+	procs: HashMap::new(),
+	globals: globals(&p.main),
+	locals: Vec::new(),
+	diagnostics: Vec::new()
+    };
+    for proc in &p.procs {
+	if let Some (first) = r.procs.insert(&proc.name, proc) {
+	    r.diagnostics.push(
+		Diagnostic::error(format!("Procedure {} is declared twice", proc.name))
+		    .with_label(proc.loc.0, "declared again here")
+		    .with_secondary(first.loc.0, "first declared here"));
+	}
+	for (i, x) in proc.params.iter().enumerate() {
+	    if proc.params[..i].contains(x) {
+		r.diagnostics.push(
+		    Diagnostic::error(
+			format!("Parameter {} of {} is declared twice", x, proc.name))
+			.with_label(proc.loc.0, "in this procedure"));
+	    }
+	}
+    }
+    for proc in &p.procs {
+	r.locals = proc.params.clone();
+	r.cmd(&proc.body, true, false)
+    }
+    r.locals.clear();
+    r.cmd(&p.main, false, false);
+    if r.diagnostics.is_empty() { Ok (()) } else { Err (r.diagnostics) }
+}
@@ -0,0 +1,139 @@
+use std::{path::Path, sync::Arc};
+use codespan::{CodeMap, FileMap, FileName};
+use crate::{assigned, context::Context, diagnostic::Diagnostic, error::Error, input::Queue, lexer,
+	    limits::{Limits, Meter}, op::{Logic, Overflow}, output::Collect, parser, resolve,
+	    step::Session, store::{Store, Value}, syntax::Program, types};
+
+// Running programs from library code.
+
+/// Which semantics runs the program.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Engine {
+    #[default]
+    Eval, // big-step
+    Step, // small-step
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    pub fold: bool,         // constant fold before running
+    pub engine: Engine,
+    pub overflow: Overflow,
+    pub logic: Logic,
+    pub limits: Limits,
+    pub input: Queue,       // values for `read`
+}
+
+/// The result of a run.
+/// The store and printed values are kept even when the run fails.
+pub struct Outcome {
+    pub store: Store,
+    pub printed: Vec<Value>,
+    pub steps: u64,
+    pub infeasible: bool, // stopped by an assumption that does not hold
+    pub diagnostics: Vec<Diagnostic>,
+    pub codemap: CodeMap, // to render the diagnostics
+}
+
+impl Outcome {
+    /// Whether the program was read and ran without errors.
+    pub fn is_ok(&self) -> bool {
+	!self.diagnostics.iter().any(Diagnostic::is_error)
+    }
+}
+
+/// Lexes and parses a file.
+pub fn parse(file: &Arc<FileMap>) -> Result<Program, Diagnostic> {
+    parser::parse(file, lexer::tokenize(file)?)
+}
+
+/// Runs the static checks on a parsed program,
+/// returning their warnings if there are no errors.
+/// Type checking also annotates the program with the widths it runs at.
+pub fn check(p: &mut Program) -> Result<Vec<Diagnostic>, Vec<Diagnostic>> {
+    resolve::check(p)?;
+    types::check(p)?;
+    Ok (assigned::check(p))
+}
+
+/// Runs the program `src`, named `name` in diagnostics.
+pub fn run_source(name: &str, src: &str, options: &Options) -> Outcome {
+    let mut codemap = CodeMap::new();
+    let file = codemap.add_filemap(FileName::virtual_(String::from(name)), String::from(src));
+    run(codemap, &file, options)
+}
+
+/// Runs the program in the file at `path`.
+pub fn run_path(path: &Path, options: &Options) -> Outcome {
+    let mut codemap = CodeMap::new();
+    match codemap.add_filemap_from_disk(path) {
+	Ok (file) => run(codemap, &file, options),
+	Err (err) => Outcome {
+	    store: Store::new(),
+	    printed: Vec::new(),
+	    steps: 0,
+	    infeasible: false,
+	    diagnostics: vec![Diagnostic::error(
+		format!("Could not read {}: {}", path.display(), err))],
+	    codemap
+	}
+    }
+}
+
+fn run(codemap: CodeMap, file: &Arc<FileMap>, options: &Options) -> Outcome {
+    let mut outcome = Outcome {
+	store: Store::new(),
+	printed: Vec::new(),
+	steps: 0,
+	infeasible: false,
+	diagnostics: Vec::new(),
+	codemap
+    };
+    if let Err (err) = options.limits.on_stack(|| execute(file, options, &mut outcome)) {
+	outcome.diagnostics.push(Diagnostic::error(
+	    format!("Could not make a stack for a call depth limit of {}: {}", options.limits.depth(), err))
+	    .with_note("a lower limit needs less stack"))
+    }
+    outcome
+}
+
+// Runs the program in `file`, recording what happens in `outcome`.
+fn execute(file: &Arc<FileMap>, options: &Options, outcome: &mut Outcome) {
+    let mut store = Store::new();
+    let mut meter = Meter::new(options.limits.clone());
+    let mut out = Collect::default();
+    let mut inp = options.input.clone();
+    let ov = options.overflow;
+    let mut warnings = Vec::new();
+    let mut infeasible = false;
+    let result = (|| -> Result<(), Vec<Diagnostic>> {
+	let mut p = parse(file)?;
+	warnings = check(&mut p)?;
+	if options.fold {
+	    p = p.fold(ov, options.logic);
+	    warnings.extend(p.refuted())
+	}
+	let cx = Context::new(ov, options.logic, &p.procs, &mut meter, &mut out, &mut inp);
+	let result = match options.engine {
+	    Engine::Eval => p.main.run(&mut store, cx),
+	    Engine::Step => {
+		let mut session = Session::new(&mut p.main, &mut store, cx);
+		loop {
+		    match session.step() {
+			None => break Ok (()),
+			Some (Err (err)) => break Err (err),
+			Some (Ok (_)) => ()
+		    }
+		}
+	    }
+	};
+	infeasible = matches!(result, Err (Error::Infeasible(..)));
+	result.map_err(|err| vec![Diagnostic::from(err)])
+    })();
+    warnings.extend(result.err().unwrap_or_default());
+    outcome.store = store;
+    outcome.printed = out.0;
+    outcome.steps = meter.steps;
+    outcome.infeasible = infeasible;
+    outcome.diagnostics = warnings;
+}
@@ -1,88 +1,413 @@
-use crate::{error::Error, store::Store, syntax::{Aexpr, Bexpr, Cmd}};
-use std::mem;
+use crate::{context::Context, diagnostic::Diagnostic, error::Error, input, integer::Integer, op::negate, store::{self, Store, Value}, syntax::{Aexpr, Bexpr, Cmd, Expr, Loc}};
+use std::{collections::HashMap, fmt, mem};
+
+// The axiom behind a reduction step.
+// Steps inside a larger term report the axiom applied to the subterm.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rule {
+    Var,
+    Neg,
+    Arith,
+    Not,
+    Compare,
+    Logic,
+    Short,
+    Assign,
+    Print,
+    Read,
+    Seq,
+    IfTrue,
+    IfFalse,
+    While,
+    Do,
+    For,
+    Loop,
+    Break,
+    Continue,
+    Exit,
+    Call,
+    Return,
+    Index,
+    Len,
+    Alloc,
+    Declare,
+    Assert,
+    Assume,
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	use Rule::*;
+	match self {
+	    Var     => write!(f, "var"),
+	    Neg     => write!(f, "neg"),
+	    Arith   => write!(f, "arith"),
+	    Not     => write!(f, "not"),
+	    Compare => write!(f, "compare"),
+	    Logic   => write!(f, "logic"),
+	    Short   => write!(f, "short-circuit"),
+	    Assign  => write!(f, "assign"),
+	    Print   => write!(f, "print"),
+	    Read    => write!(f, "read"),
+	    Seq     => write!(f, "seq"),
+	    IfTrue  => write!(f, "if-true"),
+	    IfFalse => write!(f, "if-false"),
+	    While   => write!(f, "while"),
+	    Do      => write!(f, "do"),
+	    For     => write!(f, "for"),
+	    Loop    => write!(f, "loop"),
+	    Break   => write!(f, "break"),
+	    Continue => write!(f, "continue"),
+	    Exit    => write!(f, "exit"),
+	    Call    => write!(f, "call"),
+	    Return  => write!(f, "return"),
+	    Index   => write!(f, "index"),
+	    Len     => write!(f, "len"),
+	    Alloc   => write!(f, "alloc"),
+	    Declare => write!(f, "declare"),
+	    Assert  => write!(f, "assert"),
+	    Assume  => write!(f, "assume")
+	}
+    }
+}
+
+// Each `step` reduces the term in place and returns the rule that fired,
+// or `None` if the term is a value.
+// On error the term is left as it was.
 
 impl Aexpr {
-    fn step(&mut self, s : &Store) -> Result<bool,Error> {
+    fn step(&mut self, s : &mut Store, cx : &mut Context) -> Result<Option<Rule>,Error> {
 	use Aexpr::*;
 	match self {
-	    Int (_) => Ok (false),
-	    Var (x) => s.get(&x)
-		.ok_or(Error::UnboundVariable(String::from(x.clone())))
-		.map(|z| { *self = Int(z); true }),
-	    Op (o, box Int (z1), box Int (z2)) => {
-		*self = Int (o.eval(*z1,*z2)); Ok (true)
+	    Int (_, _) => Ok (None),
+	    Var (x, l) => {
+		let z = s.int(x,*l)?;
+		*self = Int (z, *l); Ok (Some (Rule::Var))
+	    }
+	    Index (a, box Int (i, _), l) => {
+		let z = s.element(a,i,*l)?;
+		*self = Int (z, *l); Ok (Some (Rule::Index))
+	    }
+	    Index (_, e, _) => e.step(s,cx),
+	    Len (a, l) => {
+		let n = s.array(a,*l)?.len();
+		*self = Int (Integer::from(n), *l); Ok (Some (Rule::Len))
+	    }
+	    Neg (w, box Int (z, _), l) => {
+		let z = negate(z,*w,cx.ov,*l)?;
+		*self = Int (z, *l); Ok (Some (Rule::Neg))
+	    }
+	    Neg (_, e, _) => e.step(s,cx),
+	    Op (o, w, box Int (z1, _), box Int (z2, _), l) => {
+		let z = o.eval(z1,z2,*w,cx.ov,*l)?;
+		*self = Int (z, *l); Ok (Some (Rule::Arith))
+	    }
+	    Op (_, _, box Int(_, _), e2, _) => e2.step(s,cx),
+	    Op (_, _, e1, _, _) => e1.step(s,cx),
+	    // The guard is reduced first, then only the arm it picks.
+	    Cond (box Bexpr::Bool (b, _), e1, e2, _) => {
+		let (e, rule) = if *b { (e1, Rule::IfTrue) } else { (e2, Rule::IfFalse) };
+		let e = mem::replace(&mut **e, Int (Integer::from(0), Loc::default()));
+		*self = e; Ok (Some (rule))
+	    }
+	    Cond (b, _, _, _) => b.step(s,cx),
+	    Call (p, es, l) => {
+		if let Some (e) = es.iter_mut().find(|e| !matches!(e, Int (_, _))) {
+		    return e.step(s,cx)
+		}
+		let proc = cx.proc(p,es.len(),*l)?;
+		// The parameters become `let` blocks around the body,
+		// which hold their values from step to step.
+		let mut body = proc.body.clone();
+		for (x, e) in proc.params.iter().zip(es.iter()).rev() {
+		    body = Cmd::Let (x.clone(), box Expr::A (e.clone()), box body, *l)
+		}
+		*self = Body (p.clone(), box body, *l); Ok (Some (Rule::Call))
+	    }
+	    // Each step of the body runs in a frame of its own.
+	    Body (p, c, l) => match c.returned() {
+		Some (z) => { *self = Int (z.clone(), *l); Ok (Some (Rule::Return)) }
+		None if matches!(**c, Cmd::Skip (_)) =>
+		    Err (Error::NoReturn(p.clone(), *l)),
+		None => {
+		    cx.meter.call(s,*l)?;
+		    let caller = s.enter(HashMap::new());
+		    let r = c.step(s,cx);
+		    s.leave(caller);
+		    cx.meter.ret();
+		    r
+		}
 	    }
-	    Op (_, box Int(_), e2) => e2.step(s),
-	    Op (_, e1, _) => e1.step(s)
 	}
     }
 }
 
 impl Bexpr {
-    fn step(&mut self, s : &Store) -> Result<bool,Error> {
+    fn step(&mut self, s : &mut Store, cx : &mut Context) -> Result<Option<Rule>,Error> {
 	use Bexpr::*;
 	match self {
-	    Bool (_) => Ok (false),
-	    Not (box Bool (b)) => {
-		*self = Bool (!*b); Ok (true)
+	    Bool (_, _) => Ok (None),
+	    Var (x, l) => {
+		let b = s.bool(x,*l)?;
+		*self = Bool (b, *l); Ok (Some (Rule::Var))
+	    }
+	    Not (box Bool (b, _), l) => {
+		*self = Bool (!*b, *l); Ok (Some (Rule::Not))
 	    }
-	    Not (e) => e.step(s),
-	    COp (o, box Aexpr::Int(z1), box Aexpr::Int(z2)) => {
-		*self = Bool (o.eval(*z1,*z2)); Ok (true)
+	    Not (e, _) => e.step(s,cx),
+	    COp (o, box Aexpr::Int(z1, _), box Aexpr::Int(z2, _), l) => {
+		*self = Bool (o.eval(z1,z2), *l); Ok (Some (Rule::Compare))
 	    }
-	    COp (_, box Aexpr::Int (_), e2) => e2.step(s),
-	    COp (_, e1, _) => e1.step(s),
-	    BOp (o, box Bool(b1), box Bool(b2)) => {
-		*self = Bool (o.eval(*b1,*b2)); Ok (true)
+	    COp (_, box Aexpr::Int (_, _), e2, _) => e2.step(s,cx),
+	    COp (_, e1, _, _) => e1.step(s,cx),
+	    BOp (o, box Bool (b1, _), _, l) if o.short(*b1,cx.logic).is_some() => {
+		*self = Bool (*b1, *l); Ok (Some (Rule::Short))
 	    }
-	    BOp (_, box Bool (_), e2) => e2.step(s),
-	    BOp (_, e1, _) => e1.step(s)
+	    BOp (o, box Bool(b1, _), box Bool(b2, _), l) => {
+		*self = Bool (o.eval(*b1,*b2), *l); Ok (Some (Rule::Logic))
+	    }
+	    BOp (_, box Bool (_, _), e2, _) => e2.step(s,cx),
+	    BOp (_, e1, _, _) => e1.step(s,cx)
+	}
+    }
+}
+
+impl Expr {
+    fn step(&mut self, s : &mut Store, cx : &mut Context) -> Result<Option<Rule>,Error> {
+	match self {
+	    Expr::A(e) => e.step(s,cx),
+	    Expr::B(e) => e.step(s,cx)
+	}
+    }
+
+    /// The value of the expression, if it is one.
+    fn value(&self) -> Option<Value> {
+	match self {
+	    Expr::A(Aexpr::Int (z, _)) => Some (Value::Int (z.clone())),
+	    Expr::B(Bexpr::Bool (b, _)) => Some (Value::Bool (*b)),
+	    _ => None
+	}
+    }
+
+    /// The expression for a value held by a `let`.
+    fn of_value(v: Value, l: Loc) -> Expr {
+	match v {
+	    Value::Int (z) => Expr::A(Aexpr::Int (z, l)),
+	    Value::Bool (b) => Expr::B(Bexpr::Bool (b, l)),
+	    Value::Array (_) => unreachable!("let-bound variables never hold arrays")
 	}
     }
 }
 
 impl Cmd {
-    fn step(&mut self, s : &mut Store) -> Result<bool,Error> {
+    /// The value returned, if the next command to run is
+    /// `return` of an integer.
+    fn returned(&self) -> Option<&Integer> {
+	use Cmd::*;
+	match self {
+	    Return (box Aexpr::Int (z, _), _) => Some (z),
+	    Seq (c, _, _) | Loop (c, _, _) => c.returned(),
+	    Let (_, e, c, _) if e.value().is_some() => c.returned(),
+	    _ => None
+	}
+    }
+
+    /// The rule for leaving the innermost loop body,
+    /// if the next command to run is `break` or `continue`.
+    fn jump(&self) -> Option<Rule> {
+	use Cmd::*;
+	match self {
+	    Break (_) => Some (Rule::Break),
+	    Continue (_) => Some (Rule::Continue),
+	    Seq (c, _, _) => c.jump(),
+	    Let (_, e, c, _) if e.value().is_some() => c.jump(),
+	    _ => None
+	}
+    }
+
+    // A step inside a subterm is taken in place;
+    // otherwise `reduce` rewrites the command itself.
+    // Only steps inside subterms recurse through nested calls,
+    // so this frame is kept small.
+    fn step(&mut self, s : &mut Store, cx : &mut Context) -> Result<Option<Rule>,Error> {
 	use Cmd::*;
-	match mem::replace(self,Skip) {
-	    Skip => Ok (false),
-	    Ass (x, box Aexpr::Int (z)) => {
-		s.insert(&x,z); Ok (true) }
-	    Ass (x, mut e) =>
-		e.step(s)
-		.map(|b| {*self = Ass (x,e); b}),
-	    Print (box Aexpr::Int (z)) => {
-		println!("OUTPUT: {}",z); Ok (true) }
-	    Print (mut e) =>
-		e.step(s)
-		.map(|b| {*self = Print (e); b}),
-	    Seq (box Skip, box c2) => { *self = c2; Ok (true) }
-	    Seq (mut c1, c2) =>
-		c1.step(s)
-		.map(|b| {*self = Seq (c1,c2); b}),
-	    If (box Bexpr::Bool(true), box c1, _)  => { *self = c1; Ok (true) }
-	    If (box Bexpr::Bool(false), _, box c2) => { *self = c2; Ok (true) }
-	    If (mut e, c1, c2) =>
-		e.step(s)
-		.map(|b| {*self = If (e,c1,c2); b}),
-	    While (box e, box c) => {
+	match self {
+	    Ass (_, e, _) | Print (e, _) if e.value().is_none() => e.step(s,cx),
+	    // The values are reduced from left to right, then assigned at once.
+	    ParAss (_, es, _) if es.iter().any(|e| e.value().is_none()) =>
+		es.iter_mut().find(|e| e.value().is_none()).expect("not yet a value").step(s,cx),
+	    AssIndex (_, i, _, _) if !matches!(**i, Aexpr::Int (_, _)) => i.step(s,cx),
+	    AssIndex (_, _, e, _) | Alloc (_, e, _) | Return (e, _)
+		if !matches!(**e, Aexpr::Int (_, _)) => e.step(s,cx),
+	    For (_, e1, _, _, _) if !matches!(**e1, Aexpr::Int (_, _)) => e1.step(s,cx),
+	    For (_, _, e2, _, _) if !matches!(**e2, Aexpr::Int (_, _)) => e2.step(s,cx),
+	    If (e, _, _, _) | Assert (e, _, _) | Assume (e, _, _)
+		if !matches!(**e, Bexpr::Bool (_, _)) => e.step(s,cx),
+	    Seq (c, _, _) if !matches!(**c, Skip (_)) => c.step(s,cx),
+	    Loop (c, _, _) if !matches!(**c, Skip (_)) && c.jump().is_none() => c.step(s,cx),
+	    Let (_, e, _, _) if e.value().is_none() => e.step(s,cx),
+	    // The `let` node holds the current value of its variable,
+	    // which is in scope only while the body takes a step.
+	    Let (x, e, c, _) if !matches!(**c, Skip (_)) => {
+		s.push(x,e.value().expect("reduced to a value"));
+		let r = c.step(s,cx);
+		let v = s.pop().remove(x.as_str()).expect("the let scope binds its variable");
+		**e = Expr::of_value(v, e.loc()); r }
+	    _ => self.reduce(s,cx)
+	}
+    }
+
+    // Applies an axiom to a command whose subterms are values.
+    fn reduce(&mut self, s : &mut Store, cx : &mut Context) -> Result<Option<Rule>,Error> {
+	use Cmd::*;
+	match mem::replace(self,Skip (Loc::default())) {
+	    Skip (l) => { *self = Skip (l); Ok (None) }
+	    Decl (_, _, l) => { *self = Skip (l); Ok (Some (Rule::Declare)) }
+	    Ass (x, e, l) => {
+		s.insert(&x,e.value().expect("reduced to a value"));
+		*self = Skip (l); Ok (Some (Rule::Assign)) }
+	    ParAss (xs, es, l) => {
+		for (x, e) in xs.iter().zip(&es) {
+		    s.insert(x, e.value().expect("reduced to a value"))
+		}
+		*self = Skip (l); Ok (Some (Rule::Assign)) }
+	    AssIndex (a, box Aexpr::Int (i, il), box Aexpr::Int (z, zl), l) => {
+		if let Err (err) = s.set_element(&a,&i,z.clone(),l) {
+		    *self = AssIndex (a, box Aexpr::Int (i, il), box Aexpr::Int (z, zl), l);
+		    return Err (err) }
+		*self = Skip (l); Ok (Some (Rule::Assign)) }
+	    Alloc (a, box Aexpr::Int (n, nl), l) => {
+		let r = store::length(&n,l)
+		    .and_then(|k| cx.meter.alloc(s,k,l).map(|()| k))
+		    .and_then(|k| s.alloc(&a,k,l));
+		if let Err (err) = r {
+		    *self = Alloc (a, box Aexpr::Int (n, nl), l); return Err (err) }
+		*self = Skip (l); Ok (Some (Rule::Alloc)) }
+	    Print (e, l) => {
+		let v = e.value().expect("reduced to a value");
+		if let Err (err) = cx.meter.print(s,l)
+		    .and_then(|()| cx.out.print(&v).map_err(|err| Error::Output(err, l))) {
+		    *self = Print (e, l); return Err (err) }
+		*self = Skip (l); Ok (Some (Rule::Print)) }
+	    Read (x, w, l) => match input::next(cx.inp,&x,w,l) {
+		Ok (z) => { s.insert(&x,Value::Int (z)); *self = Skip (l); Ok (Some (Rule::Read)) }
+		Err (err) => { *self = Read (x, w, l); Err (err) }
+	    },
+	    Seq (box Skip (_), box c2, _) => { *self = c2; Ok (Some (Rule::Seq)) }
+	    If (box Bexpr::Bool(true, _), box c1, _, _)  => { *self = c1; Ok (Some (Rule::IfTrue)) }
+	    If (box Bexpr::Bool(false, _), _, box c2, _) => { *self = c2; Ok (Some (Rule::IfFalse)) }
+	    Assert (box Bexpr::Bool (true, _), _, l) => { *self = Skip (l); Ok (Some (Rule::Assert)) }
+	    Assert (box Bexpr::Bool (false, bl), t, l) => {
+		let err = Error::AssertionFailed(t.clone(), l);
+		*self = Assert (box Bexpr::Bool (false, bl), t, l); Err (err) }
+	    Assume (box Bexpr::Bool (true, _), _, l) => { *self = Skip (l); Ok (Some (Rule::Assume)) }
+	    Assume (box Bexpr::Bool (false, bl), t, l) => {
+		let err = Error::Infeasible(t.clone(), l);
+		*self = Assume (box Bexpr::Bool (false, bl), t, l); Err (err) }
+	    // A loop body runs inside a `loop` node,
+	    // which `break` and `continue` leave.
+	    While (box e, box c, l) => {
 		*self = If
 		    (box e.clone(),
-		     box Seq (box c.clone(),
-			      box While (box e.clone(), box c.clone())),
-		     box Skip); Ok (true) }
+		     box Loop (box c.clone(),
+			       box While (box e, box c, l),
+			       l),
+		     box Skip (l),
+		     l); Ok (Some (Rule::While)) }
+	    DoWhile (box c, box e, l) => {
+		*self = Loop
+		    (box c.clone(),
+		     box If (box e.clone(),
+			     box DoWhile (box c, box e, l),
+			     box Skip (l),
+			     l),
+		     l); Ok (Some (Rule::Do)) }
+	    For (i, box Aexpr::Int (lo, ll), box Aexpr::Int (hi, hl), box c, l) => {
+		*self = if lo > hi { Skip (l) } else {
+		    let next = if lo < hi {
+			let z = lo.checked_add(&Integer::from(1)).expect("below the bound");
+			For (i.clone(), box Aexpr::Int (z, ll), box Aexpr::Int (hi, hl), box c.clone(), l)
+		    } else {
+			Skip (l)
+		    };
+		    Seq (box Ass (i, box Expr::A(Aexpr::Int (lo, ll)), l),
+			 box Loop (box c, box next, l),
+			 l)
+		}; Ok (Some (Rule::For)) }
+	    Loop (box Skip (_), box next, _) => { *self = next; Ok (Some (Rule::Loop)) }
+	    Loop (c, next, l) => match c.jump() {
+		Some (Rule::Break) => { *self = Skip (l); Ok (Some (Rule::Break)) }
+		_ => { *self = *next; Ok (Some (Rule::Continue)) }
+	    },
+	    // Stuck: only the enclosing loop can take the jump.
+	    Break (l) => { *self = Break (l); Ok (None) }
+	    Continue (l) => { *self = Continue (l); Ok (None) }
+	    Let (_, _, _, l) => { *self = Skip (l); Ok (Some (Rule::Exit)) }
+	    // Stuck: only the enclosing call can take the value.
+	    Return (e, l) => { *self = Return (e, l); Ok (None) }
+	    c => unreachable!("{} steps inside a subterm", c)
 	}
     }
 
-    pub fn normalize(&mut self, s : &mut Store) -> Result<(),Error> {
-	loop {
-	    println!("{}",self);
-	    match self.step(s) {
-		Ok (true) => println!("-->"),
-		Ok (false) => {
-		    println!("Terminated."); return Ok (()) }
-		Err (err) => return Err (err)
-	    }
+    pub fn normalize<'a>(&'a mut self, s : &'a mut Store, cx : Context<'a>) -> Result<(),Diagnostic> {
+	let mut session = Session::new(self,s,cx);
+	println!("{}",session.cmd());
+	while let Some (step) = session.step() {
+	    println!("-->");
+	    println!("{}",step?.cmd)
+	}
+	println!("Terminated.");
+	Ok (())
+    }
+}
+
+/// A configuration reached by one step.
+pub struct Step<'s> {
+    pub rule: Rule,
+    pub cmd: &'s Cmd,
+    pub store: &'s Store,
+}
+
+/// A small-step run, driven one step at a time.
+/// Each step counts against the meter, checked before it is taken.
+pub struct Session<'a> {
+    cmd: &'a mut Cmd,
+    store: &'a mut Store,
+    cx: Context<'a>,
+    done: bool,
+}
+
+impl<'a> Session<'a> {
+    pub fn new(cmd: &'a mut Cmd, store: &'a mut Store, cx: Context<'a>) -> Self {
+	Session { cmd, store, cx, done: false }
+    }
+
+    /// The residual program.
+    pub fn cmd(&self) -> &Cmd {
+	self.cmd
+    }
+
+    pub fn store(&self) -> &Store {
+	self.store
+    }
+
+    pub fn steps(&self) -> u64 {
+	self.cx.meter.steps
+    }
+
+    /// Takes one step.
+    /// Returns `None` once the program has terminated or failed.
+    pub fn step(&mut self) -> Option<Result<Step<'_>,Error>> {
+	if self.done || matches!(self.cmd, Cmd::Skip (_)) {
+	    return None
+	}
+	let result = self.cx.meter.step(self.store,self.cmd.loc())
+	    .and_then(|()| self.cmd.step(self.store,&mut self.cx));
+	match result {
+	    Ok (Some (rule)) =>
+		Some (Ok (Step { rule, cmd: self.cmd, store: self.store })),
+	    Ok (None) => { self.done = true; None }
+	    Err (err) => { self.done = true; Some (Err (err)) }
 	}
     }
 }
@@ -1,15 +1,231 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt};
+use crate::{error::Error, integer::Integer, syntax::Loc};
 
-pub struct Store(HashMap<String, i32>);
+/// What a variable holds.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+    Int(Integer),
+    Bool(bool),
+    Array(Vec<Integer>),
+}
+
+impl Value {
+    /// Number of integers held.
+    fn size(&self) -> usize {
+	match self {
+	    Value::Int(_) | Value::Bool(_) => 1,
+	    Value::Array(a) => a.len()
+	}
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	match self {
+	    Value::Int(z) => write!(f, "{}", z),
+	    Value::Bool(b) => write!(f, "{}", b),
+	    Value::Array(a) => {
+		write!(f, "[")?;
+		for (i, z) in a.iter().enumerate() {
+		    if i > 0 { write!(f, ", ")? }
+		    write!(f, "{}", z)?
+		}
+		write!(f, "]")
+	    }
+	}
+    }
+}
+
+type Scope = HashMap<String, Value>;
+
+// A stack of scopes, innermost last.
+// The bottom scope holds the global variables.
+// A procedure call starts a frame at `base`:
+// inside it only the frame's scopes and the globals are visible.
+// Local variables (`let` and parameters) never hold arrays;
+// arrays are bound like assigned variables.
+#[derive(Clone, Debug)]
+pub struct Store {
+    scopes: Vec<Scope>,
+    base: usize,
+}
 
 impl Store {
-    pub fn new () -> Self { Store(HashMap::new()) }
-    
-    pub fn get(&self, var: &str) -> Option<i32> {
-        self.0.get(var).copied()
+    pub fn new () -> Self { Store { scopes: vec![HashMap::new()], base: 1 } }
+
+    fn visible(&self) -> impl DoubleEndedIterator<Item = &Scope> {
+	self.scopes[..1].iter().chain(&self.scopes[self.base..])
+    }
+
+    pub fn lookup(&self, var: &str) -> Option<&Value> {
+	self.visible().rev().find_map(|scope| scope.get(var))
+    }
+
+    /// The innermost visible binding of `var`,
+    /// and whether it is global.
+    fn lookup_mut(&mut self, var: &str) -> Option<(&mut Value, bool)> {
+	let (globals, frame) = self.scopes.split_at_mut(1);
+	let base = self.base - 1;
+	frame[base..].iter_mut().rev()
+	    .find_map(|scope| scope.get_mut(var)).map(|v| (v, false))
+	    .or_else(move || globals[0].get_mut(var).map(|v| (v, true)))
+    }
+
+    /// The integer held by `var`, if any.
+    pub fn get(&self, var: &str) -> Option<Integer> {
+	match self.lookup(var) {
+	    Some (Value::Int(z)) => Some (z.clone()),
+	    _ => None
+	}
+    }
+
+    /// The integer held by `var`, read at `l`.
+    pub fn int(&self, var: &str, l: Loc) -> Result<Integer, Error> {
+	match self.lookup(var) {
+	    Some (Value::Int(z)) => Ok (z.clone()),
+	    Some (_) => Err (Error::NotAnInteger(String::from(var), l)),
+	    None => Err (Error::UnboundVariable(String::from(var), l))
+	}
+    }
+
+    /// The boolean held by `var`, read at `l`.
+    pub fn bool(&self, var: &str, l: Loc) -> Result<bool, Error> {
+	match self.lookup(var) {
+	    Some (Value::Bool(b)) => Ok (*b),
+	    Some (_) => Err (Error::NotABool(String::from(var), l)),
+	    None => Err (Error::UnboundVariable(String::from(var), l))
+	}
+    }
+
+    /// The array held by `a`, read at `l`.
+    pub fn array(&self, a: &str, l: Loc) -> Result<&[Integer], Error> {
+	match self.lookup(a) {
+	    Some (Value::Array(zs)) => Ok (zs),
+	    Some (_) => Err (Error::NotAnArray(String::from(a), l)),
+	    None => Err (Error::UnboundVariable(String::from(a), l))
+	}
+    }
+
+    /// The element `a[i]`, read at `l`.
+    pub fn element(&self, a: &str, i: &Integer, l: Loc) -> Result<Integer, Error> {
+	let zs = self.array(a, l)?;
+	index(a, i, zs.len(), l).map(|i| zs[i].clone())
+    }
+
+    /// Sets `a[i]` to `value` at `l`.
+    pub fn set_element(&mut self, a: &str, i: &Integer, value: Integer, l: Loc) -> Result<(), Error> {
+	match self.lookup_mut(a) {
+	    Some ((Value::Array(zs), _)) => {
+		let i = index(a, i, zs.len(), l)?;
+		zs[i] = value;
+		Ok (())
+	    }
+	    Some (_) => Err (Error::NotAnArray(String::from(a), l)),
+	    None => Err (Error::UnboundVariable(String::from(a), l))
+	}
     }
 
-    pub fn insert(&mut self, var: &str, value: i32) {
-        self.0.insert(String::from(var), value);
+    /// Updates the innermost visible binding of `var`,
+    /// or binds it globally if it is not bound yet.
+    pub fn insert(&mut self, var: &str, value: Value) {
+	match self.lookup_mut(var) {
+	    Some ((v, _)) => *v = value,
+	    None => { self.scopes[0].insert(String::from(var), value); }
+	}
+    }
+
+    /// Binds `a` to an array of `n` zeros at `l`,
+    /// where `insert` would bind it, unless that is a local variable.
+    pub fn alloc(&mut self, a: &str, n: usize, l: Loc) -> Result<(), Error> {
+	let zs = Value::Array(vec![Integer::from(0); n]);
+	match self.lookup_mut(a) {
+	    Some ((_, false)) => Err (Error::NotAnArray(String::from(a), l)),
+	    Some ((v, true)) => { *v = zs; Ok (()) }
+	    None => { self.scopes[0].insert(String::from(a), zs); Ok (()) }
+	}
+    }
+
+    /// Enters a scope binding `var` to `value`,
+    /// shadowing any outer binding.
+    pub fn push(&mut self, var: &str, value: Value) {
+	let mut scope = HashMap::new();
+	scope.insert(String::from(var), value);
+	self.scopes.push(scope)
+    }
+
+    /// Leaves the innermost scope of the current frame,
+    /// returning its bindings.
+    pub fn pop(&mut self) -> HashMap<String, Value> {
+	if self.scopes.len() > self.base { self.scopes.pop().unwrap() } else { HashMap::new() }
+    }
+
+    /// Starts a frame binding the parameters of a call,
+    /// and returns the frame to go back to.
+    pub fn enter(&mut self, params: HashMap<String, Integer>) -> usize {
+	let caller = self.base;
+	self.base = self.scopes.len();
+	self.scopes.push(params.into_iter().map(|(x, z)| (x, Value::Int(z))).collect());
+	caller
+    }
+
+    /// Drops the current frame and goes back to `caller`.
+    pub fn leave(&mut self, caller: usize) {
+	self.scopes.truncate(self.base);
+	self.base = caller
+    }
+
+    /// The visible bindings, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Value)> {
+	let mut visible = HashMap::new();
+	for scope in self.visible() {
+	    visible.extend(scope.iter().map(|(x, z)| (x.as_str(), z)))
+	}
+	visible.into_iter()
+    }
+
+    /// Number of integers held, counting each array element
+    /// and shadowed bindings.
+    pub fn len(&self) -> usize {
+	self.scopes.iter().flat_map(HashMap::values).map(Value::size).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+	self.len() == 0
+    }
+}
+
+impl Default for Store {
+    fn default() -> Self { Store::new() }
+}
+
+// Each scope is printed with its bindings sorted by name,
+// outermost first, e.g. `{x = 1, y = 2} {x = 3}`.
+impl fmt::Display for Store {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	for (i, scope) in self.scopes.iter().enumerate() {
+	    if i > 0 { write!(f, " ")? }
+	    let mut vars : Vec<_> = scope.iter().collect();
+	    vars.sort_by_key(|(x, _)| *x);
+	    write!(f, "{{")?;
+	    for (j, (x, z)) in vars.into_iter().enumerate() {
+		if j > 0 { write!(f, ", ")? }
+		write!(f, "{} = {}", x, z)?
+	    }
+	    write!(f, "}}")?
+	}
+	Ok (())
+    }
+}
+
+/// Checks an array length `n` given at `l`.
+pub fn length(n: &Integer, l: Loc) -> Result<usize, Error> {
+    n.to_usize().ok_or_else(|| Error::BadLength(n.clone(), l))
+}
+
+// Checks the index `i` into the array `a` of length `n`.
+fn index(a: &str, i: &Integer, n: usize, l: Loc) -> Result<usize, Error> {
+    match i.to_usize() {
+	Some (i) if i < n => Ok (i),
+	_ => Err (Error::IndexOutOfBounds(String::from(a), i.clone(), n, l))
     }
 }
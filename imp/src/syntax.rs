@@ -1,11 +1,38 @@
-use std::{cmp::{PartialEq, Eq}, fmt /*, marker::StructuralEq */};
+use std::{cmp::{PartialEq, Eq}, fmt, iter, mem /*, marker::StructuralEq */};
+use codespan::{ByteIndex, ByteSpan};
+use crate::{integer::Integer, types::{Type, Width}};
+
+// Source locations.
+// Locations never take part in equality,
+// so the same term written in two places compares equal.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Loc(pub ByteSpan);
+
+impl Loc {
+    pub fn new(start: ByteIndex, end: ByteIndex) -> Self {
+	Loc(ByteSpan::new(start, end))
+    }
+
+    /// Smallest location covering both `self` and `other`.
+    pub fn to(self, other: Loc) -> Self {
+	Loc(self.0.to(other.0))
+    }
+}
+
+impl PartialEq for Loc {
+    fn eq(&self, _: &Loc) -> bool { true }
+}
+
+impl Eq for Loc {}
 
 // Arithmetic operators.
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Aop {
     Add,
     Sub,
     Mul,
+    Div,
+    Mod,
 }
 
 impl fmt::Display for Aop {
@@ -13,7 +40,9 @@ impl fmt::Display for Aop {
 	match self {
 	    Aop::Add => write!(f, "+"),
 	    Aop::Sub => write!(f, "-"),
-	    Aop::Mul => write!(f, "*")
+	    Aop::Mul => write!(f, "*"),
+	    Aop::Div => write!(f, "/"),
+	    Aop::Mod => write!(f, "%")
 	}
     }
 }
@@ -21,39 +50,87 @@ impl fmt::Display for Aop {
 // Arithmetic expressions.
 #[derive(Clone, PartialEq, Eq)]
 pub enum Aexpr {
-    Int(i32),
-    Var(String),
-    Op(Aop, Box<Aexpr>, Box<Aexpr>),
+    Int(Integer, Loc),
+    Var(String, Loc),
+    // Arithmetic at the width set by the type checker.
+    Neg(Width, Box<Aexpr>, Loc),
+    Op(Aop, Width, Box<Aexpr>, Box<Aexpr>, Loc),
+    Call(String, Vec<Aexpr>, Loc),
+    Index(String, Box<Aexpr>, Loc), // a[i]
+    Len(String, Loc),
+    Cond(Box<Bexpr>, Box<Aexpr>, Box<Aexpr>, Loc), // if b then e1 else e2
+    // A running procedure body.
+    // Only arises during small-step execution.
+    Body(String, Box<Cmd>, Loc),
+}
+
+impl Aexpr {
+    pub fn loc(&self) -> Loc {
+	match self {
+	    Aexpr::Int(_,l) | Aexpr::Var(_,l) |
+	    Aexpr::Neg(_,_,l) | Aexpr::Op(_,_,_,_,l) |
+	    Aexpr::Call(_,_,l) | Aexpr::Body(_,_,l) |
+	    Aexpr::Index(_,_,l) | Aexpr::Len(_,l) | Aexpr::Cond(_,_,_,l) => *l
+	}
+    }
+}
+
+// Comma-separated list.
+fn commas<T: fmt::Display>(f: &mut fmt::Formatter, xs: &[T]) -> fmt::Result {
+    for (i, x) in xs.iter().enumerate() {
+	if i > 0 { write!(f, ", ")? }
+	write!(f, "{}", x)?
+    }
+    Ok (())
 }
 
 impl fmt::Display for Aexpr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 	match self {
-	    Aexpr::Int(z) => write!(f, "{}", z),
-	    Aexpr::Var(x) => write!(f, "{}", x),
-	    Aexpr::Op(o,e1,e2) => write!(f, "({} {} {})", e1, o, e2)
+	    Aexpr::Int(z,_) => write!(f, "{}", z),
+	    Aexpr::Var(x,_) => write!(f, "{}", x),
+	    Aexpr::Neg(_,e,_) => write!(f, "-{}", e),
+	    Aexpr::Op(o,_,e1,e2,_) => write!(f, "({} {} {})", e1, o, e2),
+	    Aexpr::Call(p,es,_) => {
+		write!(f, "{}(", p)?;
+		commas(f, es)?;
+		write!(f, ")")
+	    }
+	    Aexpr::Index(a,i,_) => write!(f, "{}[{}]", a, i),
+	    Aexpr::Len(a,_) => write!(f, "len({})", a),
+	    Aexpr::Cond(b,e1,e2,_) => write!(f, "(if {} then {} else {})", b, e1, e2),
+	    Aexpr::Body(p,c,_) =>
+		write!(f, "call {} {{\n{}\n}}", p, c)
 	}
     }
 }
 
 // Comparison operators.
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Cop {
     Eq,
+    Ne,
     Lt,
+    Le,
+    Gt,
+    Ge,
 }
 
 impl fmt::Display for Cop {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 	match self {
 	    Cop::Eq => write!(f, "=?"),
-	    Cop::Lt => write!(f, "<?")
+	    Cop::Ne => write!(f, "!=?"),
+	    Cop::Lt => write!(f, "<?"),
+	    Cop::Le => write!(f, "<=?"),
+	    Cop::Gt => write!(f, ">?"),
+	    Cop::Ge => write!(f, ">=?")
 	}
     }
 }
 
 // Boolean operators.
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Bop {
     And,
     Or,
@@ -71,19 +148,56 @@ impl fmt::Display for Bop {
 // Boolean expressions.
 #[derive(Clone, PartialEq, Eq)]
 pub enum Bexpr {
-    Bool(bool),
-    Not (Box<Bexpr>),
-    COp(Cop, Box<Aexpr>, Box<Aexpr>),
-    BOp(Bop, Box<Bexpr>, Box<Bexpr>),
+    Bool(bool, Loc),
+    Var(String, Loc),
+    Not (Box<Bexpr>, Loc),
+    COp(Cop, Box<Aexpr>, Box<Aexpr>, Loc),
+    BOp(Bop, Box<Bexpr>, Box<Bexpr>, Loc),
+}
+
+impl Bexpr {
+    pub fn loc(&self) -> Loc {
+	match self {
+	    Bexpr::Bool(_,l) | Bexpr::Var(_,l) | Bexpr::Not(_,l) |
+	    Bexpr::COp(_,_,_,l) | Bexpr::BOp(_,_,_,l) => *l
+	}
+    }
 }
 
 impl fmt::Display for Bexpr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 	match self {
-	    Bexpr::Bool(b) => write!(f, "{}", b),
-	    Bexpr::Not (e) => write!(f, "(!{})", e),
-	    Bexpr::COp(o,e1,e2) => write!(f, "({} {} {})", e1, o, e2),
-	    Bexpr::BOp(o,e1,e2) => write!(f, "({} {} {})", e1, o, e2)
+	    Bexpr::Bool(b,_) => write!(f, "{}", b),
+	    Bexpr::Var(x,_) => write!(f, "{}", x),
+	    Bexpr::Not (e,_) => write!(f, "(!{})", e),
+	    Bexpr::COp(o,e1,e2,_) => write!(f, "({} {} {})", e1, o, e2),
+	    Bexpr::BOp(o,e1,e2,_) => write!(f, "({} {} {})", e1, o, e2)
+	}
+    }
+}
+
+// Expressions of either kind,
+// where both integers and booleans may appear.
+#[derive(Clone, PartialEq, Eq)]
+pub enum Expr {
+    A(Aexpr),
+    B(Bexpr),
+}
+
+impl Expr {
+    pub fn loc(&self) -> Loc {
+	match self {
+	    Expr::A(e) => e.loc(),
+	    Expr::B(e) => e.loc()
+	}
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	match self {
+	    Expr::A(e) => write!(f, "{}", e),
+	    Expr::B(e) => write!(f, "{}", e)
 	}
     }
 }
@@ -91,25 +205,164 @@ impl fmt::Display for Bexpr {
 // Commands.
 #[derive(Clone, PartialEq, Eq)]
 pub enum Cmd {
-    Skip,
-    Ass(String, Box<Aexpr>),
-    Print(Box<Aexpr>),
-    Seq(Box<Cmd>, Box<Cmd>),
-    If(Box<Bexpr>, Box<Cmd>, Box<Cmd>),
-    While(Box<Bexpr>, Box<Cmd>),
+    Skip(Loc),
+    Decl(String, Type, Loc), // x : t
+    Ass(String, Box<Expr>, Loc),
+    ParAss(Vec<String>, Vec<Expr>, Loc), // x1, ..., xn := e1, ..., en
+    AssIndex(String, Box<Aexpr>, Box<Aexpr>, Loc), // a[i] := e
+    Alloc(String, Box<Aexpr>, Loc), // array a[n]
+    Print(Box<Expr>, Loc),
+    Read(String, Width, Loc), // width set by the type checker
+    Seq(Box<Cmd>, Box<Cmd>, Loc),
+    If(Box<Bexpr>, Box<Cmd>, Box<Cmd>, Loc),
+    While(Box<Bexpr>, Box<Cmd>, Loc),
+    For(String, Box<Aexpr>, Box<Aexpr>, Box<Cmd>, Loc), // for i := a to b { c }
+    DoWhile(Box<Cmd>, Box<Bexpr>, Loc), // do { c } while b
+    Break(Loc),
+    Continue(Loc),
+    Let(String, Box<Expr>, Box<Cmd>, Loc), // local variable
+    Return(Box<Aexpr>, Loc),
+    Assert(Box<Bexpr>, String, Loc), // condition and its text as written
+    Assume(Box<Bexpr>, String, Loc),
+    // A loop body running, then what the loop does next.
+    // Only arises during small-step execution.
+    Loop(Box<Cmd>, Box<Cmd>, Loc),
+}
+
+impl Cmd {
+    pub fn loc(&self) -> Loc {
+	match self {
+	    Cmd::Skip(l) | Cmd::Decl(_,_,l) | Cmd::Ass(_,_,l) | Cmd::ParAss(_,_,l) |
+	    Cmd::Print(_,l) | Cmd::Read(_,_,l) |
+	    Cmd::Seq(_,_,l) | Cmd::If(_,_,_,l) | Cmd::While(_,_,l) |
+	    Cmd::Let(_,_,_,l) | Cmd::Return(_,l) |
+	    Cmd::Assert(_,_,l) | Cmd::Assume(_,_,l) |
+	    Cmd::For(_,_,_,_,l) | Cmd::DoWhile(_,_,l) |
+	    Cmd::Break(l) | Cmd::Continue(l) | Cmd::Loop(_,_,l) |
+	    Cmd::AssIndex(_,_,_,l) | Cmd::Alloc(_,_,l) => *l
+	}
+    }
+
+    /// The commands a sequence runs one after the other.
+    /// Long programs are long right-nested sequences,
+    /// so passes walk them with this rather than by recursion.
+    pub fn sequence(&self) -> impl Iterator<Item = &Cmd> {
+	let mut next = Some (self);
+	iter::from_fn(move || match next.take()? {
+	    Cmd::Seq(c1, c2, _) => { next = Some (&**c2); Some (&**c1) }
+	    c => Some (c)
+	})
+    }
+
+    pub fn sequence_mut(&mut self) -> impl Iterator<Item = &mut Cmd> {
+	let mut next = Some (self);
+	iter::from_fn(move || match next.take()? {
+	    Cmd::Seq(c1, c2, _) => { next = Some (&mut **c2); Some (&mut **c1) }
+	    c => Some (c)
+	})
+    }
 }
 
 impl fmt::Display for Cmd {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 	match self {
-	    Cmd::Skip => write!(f, "skip"),
-	    Cmd::Ass(x,e) => write!(f, "{} := {}", x, e),
-	    Cmd::Print(e) => write!(f, "print {}", e),
-	    Cmd::Seq(c1,c2) => write!(f, "{};\n{}", c1, c2),
-	    Cmd::If(e,c1,c2) =>
+	    Cmd::Skip(_) => write!(f, "skip"),
+	    Cmd::Decl(x,t,_) => write!(f, "{} : {}", x, t),
+	    Cmd::Ass(x,e,_) => write!(f, "{} := {}", x, e),
+	    Cmd::ParAss(xs,es,_) => {
+		commas(f, xs)?;
+		write!(f, " := ")?;
+		commas(f, es)
+	    }
+	    Cmd::AssIndex(a,i,e,_) => write!(f, "{}[{}] := {}", a, i, e),
+	    Cmd::Alloc(a,n,_) => write!(f, "array {}[{}]", a, n),
+	    Cmd::Print(e,_) => write!(f, "print {}", e),
+	    Cmd::Read(x,_,_) => write!(f, "read {}", x),
+	    Cmd::Seq(_,_,_) => {
+		for (i, c) in self.sequence().enumerate() {
+		    if i > 0 { writeln!(f, ";")? }
+		    write!(f, "{}", c)?
+		}
+		Ok (())
+	    }
+	    Cmd::If(e,c1,c2,_) =>
 		write!(f, "if {} {}\n{}\n{} else {}\n{}\n{}", e, "{", c1, "}", "{", c2, "}"),
-	    Cmd::While(e,c) =>
-		write!(f, "while {} {}\n{}\n{}", e, "{", c, "}")
+	    Cmd::While(e,c,_) =>
+		write!(f, "while {} {}\n{}\n{}", e, "{", c, "}"),
+	    Cmd::For(i,e1,e2,c,_) =>
+		write!(f, "for {} := {} to {} {{\n{}\n}}", i, e1, e2, c),
+	    Cmd::DoWhile(c,e,_) =>
+		write!(f, "do {{\n{}\n}} while {}", c, e),
+	    Cmd::Break(_) => write!(f, "break"),
+	    Cmd::Continue(_) => write!(f, "continue"),
+	    Cmd::Loop(c1,c2,_) =>
+		write!(f, "loop {{\n{}\n}} then {{\n{}\n}}", c1, c2),
+	    Cmd::Let(x,e,c,_) =>
+		write!(f, "let {} := {} in {{\n{}\n}}", x, e, c),
+	    Cmd::Return(e,_) => write!(f, "return {}", e),
+	    Cmd::Assert(e,_,_) => write!(f, "assert {}", e),
+	    Cmd::Assume(e,_,_) => write!(f, "assume {}", e)
+	}
+    }
+}
+
+// Procedures.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Proc {
+    pub name: String,
+    pub params: Vec<String>,
+    pub body: Cmd,
+    pub loc: Loc,
+}
+
+impl fmt::Display for Proc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	write!(f, "proc {}(", self.name)?;
+	commas(f, &self.params)?;
+	write!(f, ") {{\n{}\n}}", self.body)
+    }
+}
+
+// Programs: procedure declarations, then the main command.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Program {
+    pub procs: Vec<Proc>,
+    pub main: Cmd,
+}
+
+// Dropping a command recurses on its subcommands,
+// so long programs are taken apart one command at a time.
+impl Drop for Program {
+    fn drop(&mut self) {
+	let mut cs : Vec<Cmd> = self.procs.iter_mut().map(|p| &mut p.body)
+	    .chain(iter::once(&mut self.main))
+	    .map(|c| mem::replace(c, Cmd::Skip(Loc::default())))
+	    .collect();
+	while let Some (c) = cs.pop() {
+	    match c {
+		Cmd::Seq(c1, c2, _) | Cmd::If(_, c1, c2, _) | Cmd::Loop(c1, c2, _) => {
+		    cs.push(*c1);
+		    cs.push(*c2)
+		}
+		Cmd::While(_, c, _) | Cmd::For(_, _, _, c, _) | Cmd::DoWhile(c, _, _) |
+		Cmd::Let(_, _, c, _) => cs.push(*c),
+		_ => ()
+	    }
+	}
+    }
+}
+
+impl Program {
+    pub fn proc(&self, name: &str) -> Option<&Proc> {
+	self.procs.iter().find(|p| p.name == name)
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	for p in &self.procs {
+	    writeln!(f, "{}", p)?
 	}
+	write!(f, "{}", self.main)
     }
 }
@@ -0,0 +1,487 @@
+use std::{collections::HashMap, fmt, str::FromStr};
+use crate::{diagnostic::Diagnostic, integer::Integer, op::Overflow,
+	    syntax::{Aexpr, Bexpr, Cmd, Expr, Loc, Program}};
+
+// Types, checked between parsing and running a program.
+// A global variable has one type throughout the program:
+// the one it is declared with (`x : i8`),
+// or else the type of the first value assigned to it that has one.
+// Only `array` makes an array, and arrays are never assigned whole.
+// Integer literals take the type their context expects, `int` by default.
+// A `let` variable has the type of its initial value,
+// and procedure parameters and results are `int`.
+// Arithmetic never mixes widths,
+// and each operation is annotated with the width it runs at.
+
+/// Integer types.
+/// `int` is 32-bit, or arbitrary precision with the `bigint` feature;
+/// the others always have the width they name.
+/// A literal must fit in the type it takes,
+/// so without `bigint` no literal is wider than `i64`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Width {
+    #[default]
+    Int,
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+}
+
+impl Width {
+    /// The smallest and largest integers of the type, if it is bounded.
+    pub fn bounds(self) -> Option<(Integer, Integer)> {
+	use Width::*;
+	let (lo, hi) : (i64, i64) = match self {
+	    Int if cfg!(feature = "bigint") => return None,
+	    Int | I32 => (i32::MIN.into(), i32::MAX.into()),
+	    I8  => (i8::MIN.into(), i8::MAX.into()),
+	    I16 => (i16::MIN.into(), i16::MAX.into()),
+	    I64 => (i64::MIN, i64::MAX),
+	    U8  => (0, u8::MAX.into()),
+	    U16 => (0, u16::MAX.into()),
+	    U32 => (0, u32::MAX.into())
+	};
+	Some ((Integer::from(lo), Integer::from(hi)))
+    }
+
+    /// Narrows `z` to the type under overflow policy `ov`,
+    /// or `None` if it does not fit and `ov` traps.
+    pub fn fit(self, z: Integer, ov: Overflow) -> Option<Integer> {
+	match self.bounds() {
+	    Some ((lo, hi)) if z < lo || z > hi => match ov {
+		Overflow::Trap => None,
+		Overflow::Wrap => Some (z.wrap(&lo, &hi)),
+		Overflow::Saturate => Some (if z < lo { lo } else { hi })
+	    },
+	    _ => Some (z)
+	}
+    }
+}
+
+impl fmt::Display for Width {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	use Width::*;
+	match self {
+	    Int => write!(f, "int"),
+	    I8  => write!(f, "i8"),
+	    I16 => write!(f, "i16"),
+	    I32 => write!(f, "i32"),
+	    I64 => write!(f, "i64"),
+	    U8  => write!(f, "u8"),
+	    U16 => write!(f, "u16"),
+	    U32 => write!(f, "u32")
+	}
+    }
+}
+
+/// Types of variables.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Type {
+    Bool,
+    Int(Width),
+    Array, // of `int`
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	match self {
+	    Type::Bool => write!(f, "bool"),
+	    Type::Int(w) => write!(f, "{}", w),
+	    Type::Array => write!(f, "array")
+	}
+    }
+}
+
+impl FromStr for Type {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+	use Width::*;
+	let w = match s {
+	    "bool" => return Ok (Type::Bool),
+	    "int" => Int,
+	    "i8"  => I8,
+	    "i16" => I16,
+	    "i32" => I32,
+	    "i64" => I64,
+	    "u8"  => U8,
+	    "u16" => U16,
+	    "u32" => U32,
+	    _ => return Err (format!("Unknown type {}", s))
+	};
+	Ok (Type::Int(w))
+    }
+}
+
+struct Checker {
+    globals: HashMap<String, Type>,
+    locals: Vec<(String, Type)>, // `let` variables and parameters, innermost last
+    changed: bool,               // whether inference typed a global
+    diagnostics: Vec<Diagnostic>,
+}
+
+fn error(message: String, l: Loc, label: &str) -> Diagnostic {
+    Diagnostic::error(message).with_label(l.0, label)
+}
+
+impl Checker {
+    fn lookup(&self, x: &str) -> Option<Type> {
+	self.locals.iter().rev().find(|(y, _)| y == x).map(|(_, t)| *t)
+	    .or_else(|| self.globals.get(x).copied())
+    }
+
+    fn is_local(&self, x: &str) -> bool {
+	self.locals.iter().any(|(y, _)| y == x)
+    }
+
+    // Type of `x`, for a variable never given one.
+    fn var(&self, x: &str) -> Type {
+	self.lookup(x).unwrap_or(Type::Int(Width::Int))
+    }
+
+    // Declarations.
+
+    fn declare(&mut self, c: &Cmd) {
+	use Cmd::*;
+	match c {
+	    Decl (x, t, l) => match self.globals.insert(x.clone(), *t) {
+		Some (u) if u != *t => self.diagnostics.push(
+		    error(format!("{} is declared as both {} and {}", x, u, t),
+			  *l, "conflicting declaration")),
+		_ => ()
+	    },
+	    Seq (_, _, _) => for c in c.sequence() { self.declare(c) },
+	    If (_, c1, c2, _) | Loop (c1, c2, _) => {
+		self.declare(c1);
+		self.declare(c2)
+	    }
+	    While (_, c, _) | For (_, _, _, c, _) | DoWhile (c, _, _) | Let (_, _, c, _) =>
+		self.declare(c),
+	    _ => ()
+	}
+    }
+
+    // Inference.
+    // The type of an expression as far as it is known yet,
+    // without reporting errors.
+
+    fn infer_aexpr(&self, e: &Aexpr) -> Option<Type> {
+	use Aexpr::*;
+	match e {
+	    Int (_, _) => None,
+	    Var (x, _) => self.lookup(x),
+	    Neg (_, e, _) => self.infer_aexpr(e),
+	    Op (_, _, e1, e2, _) | Cond (_, e1, e2, _) =>
+		self.infer_aexpr(e1).or_else(|| self.infer_aexpr(e2)),
+	    Call (_, _, _) | Index (_, _, _) | Len (_, _) | Body (_, _, _) =>
+		Some (Type::Int(Width::Int))
+	}
+    }
+
+    fn infer_expr(&self, e: &Expr) -> Option<Type> {
+	match e {
+	    Expr::A(e) => self.infer_aexpr(e),
+	    Expr::B(_) => Some (Type::Bool)
+	}
+    }
+
+    // Types the globals assigned in `c`.
+    fn infer(&mut self, c: &Cmd) {
+	use Cmd::*;
+	match c {
+	    // Only `array` makes an array.
+	    Ass (x, e, _) if !self.is_local(x) && !self.globals.contains_key(x) => {
+		if let Some (t) = self.infer_expr(e).filter(|t| *t != Type::Array) {
+		    self.globals.insert(x.clone(), t);
+		    self.changed = true
+		}
+	    }
+	    ParAss (xs, es, _) => {
+		for (x, e) in xs.iter().zip(es) {
+		    if !self.is_local(x) && !self.globals.contains_key(x) {
+			if let Some (t) = self.infer_expr(e).filter(|t| *t != Type::Array) {
+			    self.globals.insert(x.clone(), t);
+			    self.changed = true
+			}
+		    }
+		}
+	    }
+	    Alloc (a, _, _) if !self.is_local(a) && !self.globals.contains_key(a) => {
+		self.globals.insert(a.clone(), Type::Array);
+		self.changed = true
+	    }
+	    For (i, e1, e2, c, _) => {
+		if !self.is_local(i) && !self.globals.contains_key(i) {
+		    if let Some (t) = self.infer_aexpr(e1).or_else(|| self.infer_aexpr(e2)) {
+			self.globals.insert(i.clone(), t);
+			self.changed = true
+		    }
+		}
+		self.infer(c)
+	    }
+	    Seq (_, _, _) => for c in c.sequence() { self.infer(c) },
+	    If (_, c1, c2, _) | Loop (c1, c2, _) => {
+		self.infer(c1);
+		self.infer(c2)
+	    }
+	    While (_, c, _) | DoWhile (c, _, _) => self.infer(c),
+	    Let (x, e, c, _) => {
+		let t = self.infer_expr(e).unwrap_or(Type::Int(Width::Int));
+		self.locals.push((x.clone(), t));
+		self.infer(c);
+		self.locals.pop();
+	    }
+	    _ => ()
+	}
+    }
+
+    // Checking.
+    // Reports type errors and annotates the program with widths.
+
+    // The width of an integer expression,
+    // or `None` if it is made of literals only.
+    fn width(&self, e: &Aexpr) -> Result<Option<Width>, Diagnostic> {
+	use Aexpr::*;
+	match e {
+	    Int (_, _) => Ok (None),
+	    Var (x, l) => match self.var(x) {
+		Type::Int(w) => Ok (Some (w)),
+		t => Err (error(format!("Expected an integer, but {} has type {}", x, t),
+				*l, "not an integer"))
+	    },
+	    Neg (_, e, _) => self.width(e),
+	    Op (_, _, e1, e2, l) => match (self.width(e1)?, self.width(e2)?) {
+		(Some (w1), Some (w2)) if w1 != w2 =>
+		    Err (error(format!("Mismatched integer types {} and {}", w1, w2),
+			       *l, "operands of different types")),
+		(w1, w2) => Ok (w1.or(w2))
+	    },
+	    Cond (_, e1, e2, l) => match (self.width(e1)?, self.width(e2)?) {
+		(Some (w1), Some (w2)) if w1 != w2 =>
+		    Err (error(format!("Mismatched integer types {} and {}", w1, w2),
+			       *l, "arms of different types")),
+		(w1, w2) => Ok (w1.or(w2))
+	    },
+	    Call (_, _, _) | Index (_, _, _) | Len (_, _) | Body (_, _, _) =>
+		Ok (Some (Width::Int))
+	}
+    }
+
+    fn array(&mut self, a: &str, l: Loc) {
+	match self.var(a) {
+	    Type::Array => (),
+	    t => self.diagnostics.push(
+		error(format!("Expected an array, but {} has type {}", a, t), l, "not an array"))
+	}
+    }
+
+    // Checks an integer expression of type `want`, if given,
+    // and returns its type.
+    fn aexpr(&mut self, e: &mut Aexpr, want: Option<Width>) -> Width {
+	let found = match self.width(e) {
+	    Ok (w) => w,
+	    Err (d) => {
+		self.diagnostics.push(d);
+		return want.unwrap_or_default()
+	    }
+	};
+	let w = match (found, want) {
+	    (Some (f), Some (w)) if f != w => {
+		self.diagnostics.push(
+		    error(format!("Expected {}, found {}", w, f), e.loc(), "wrong type"));
+		w
+	    }
+	    (f, w) => f.or(w).unwrap_or_default()
+	};
+	self.annotate(e, w);
+	w
+    }
+
+    // Sets the width of every operation in `e`.
+    fn annotate(&mut self, e: &mut Aexpr, w: Width) {
+	use Aexpr::*;
+	match e {
+	    Int (z, l) => {
+		if w.fit(z.clone(), Overflow::Trap).is_none() {
+		    self.diagnostics.push(
+			error(format!("Literal {} does not fit in {}", z, w),
+			      *l, "out of range"))
+		}
+	    }
+	    Var (_, _) | Body (_, _, _) => (),
+	    Neg (v, e, _) => { *v = w; self.annotate(e, w) }
+	    Op (_, v, e1, e2, _) => {
+		*v = w;
+		self.annotate(e1, w);
+		self.annotate(e2, w)
+	    }
+	    Cond (b, e1, e2, _) => {
+		self.bexpr(b);
+		self.annotate(e1, w);
+		self.annotate(e2, w)
+	    }
+	    Call (_, es, _) => for e in es { self.aexpr(e, Some (Width::Int)); }
+	    Index (a, i, l) => { self.array(a, *l); self.aexpr(i, None); }
+	    Len (a, l) => self.array(a, *l)
+	}
+    }
+
+    fn bexpr(&mut self, e: &mut Bexpr) {
+	use Bexpr::*;
+	match e {
+	    Bool (_, _) => (),
+	    Var (x, l) => match self.var(x) {
+		Type::Bool => (),
+		t => self.diagnostics.push(
+		    error(format!("Expected a bool, but {} has type {}", x, t), *l, "not a bool"))
+	    },
+	    Not (e, _) => self.bexpr(e),
+	    COp (_, e1, e2, _) => {
+		// Either operand may fix the width of the other.
+		let w = self.width(e1).ok().flatten()
+		    .or_else(|| self.width(e2).ok().flatten());
+		self.aexpr(e1, w);
+		self.aexpr(e2, w);
+	    }
+	    BOp (_, e1, e2, _) => { self.bexpr(e1); self.bexpr(e2) }
+	}
+    }
+
+    // Checks an expression where either kind may appear,
+    // of type `want` if given, and returns its type.
+    fn expr(&mut self, e: &mut Expr, want: Option<Type>) -> Type {
+	// A variable on its own parses as an integer.
+	if let Expr::A(Aexpr::Var(x, l)) = e {
+	    if self.var(x) == Type::Bool {
+		*e = Expr::B(Bexpr::Var(x.clone(), *l))
+	    }
+	}
+	match (e, want) {
+	    (Expr::B(b), None) | (Expr::B(b), Some (Type::Bool)) => {
+		self.bexpr(b);
+		Type::Bool
+	    }
+	    (Expr::A(a), None) => match a {
+		Aexpr::Var(x, _) if self.var(x) == Type::Array => Type::Array,
+		a => Type::Int(self.aexpr(a, None))
+	    },
+	    (Expr::A(a), Some (Type::Int(w))) => Type::Int(self.aexpr(a, Some (w))),
+	    (e, Some (t)) => {
+		let found = self.expr(e, None);
+		self.diagnostics.push(
+		    error(format!("Expected {}, found {}", t, found), e.loc(), "wrong type"));
+		t
+	    }
+	}
+    }
+
+    // Checks the assignment of `e` to `x` at `l`.
+    // Arrays are changed element by element, never assigned whole.
+    fn assign(&mut self, x: &str, e: &mut Expr, l: Loc) {
+	let t = self.var(x);
+	let array = matches!(e, Expr::A(Aexpr::Var(y, _)) if self.var(y) == Type::Array);
+	if t == Type::Array || array {
+	    self.diagnostics.push(
+		error(String::from("Cannot assign an array"), l, "assigns an array")
+		    .with_note("arrays are made with `array` and changed element by element"))
+	} else {
+	    self.expr(e, Some (t));
+	}
+    }
+
+    fn cmd(&mut self, c: &mut Cmd) {
+	use Cmd::*;
+	match c {
+	    Skip (_) | Decl (_, _, _) | Break (_) | Continue (_) => (),
+	    Ass (x, e, l) => self.assign(x, e, *l),
+	    ParAss (xs, es, l) => for (x, e) in xs.iter().zip(es) {
+		self.assign(x, e, *l)
+	    },
+	    AssIndex (a, i, e, l) => {
+		self.array(a, *l);
+		self.aexpr(i, None);
+		self.aexpr(e, Some (Width::Int));
+	    }
+	    Alloc (a, n, l) => { self.array(a, *l); self.aexpr(n, None); }
+	    Print (e, l) => {
+		if self.expr(e, None) == Type::Array {
+		    self.diagnostics.push(
+			error(String::from("Cannot print an array"), *l, "prints an array"))
+		}
+	    }
+	    Read (x, w, l) => match self.var(x) {
+		Type::Int(v) => *w = v,
+		t => self.diagnostics.push(
+		    error(format!("Cannot read into {} of type {}", x, t), *l, "reads a non-integer"))
+	    },
+	    Seq (_, _, _) => for c in c.sequence_mut() { self.cmd(c) },
+	    Loop (c1, c2, _) => { self.cmd(c1); self.cmd(c2) }
+	    If (e, c1, c2, _) => { self.bexpr(e); self.cmd(c1); self.cmd(c2) }
+	    While (e, c, _) | DoWhile (c, e, _) => { self.bexpr(e); self.cmd(c) }
+	    // The bounds have the type of the counter.
+	    For (i, e1, e2, c, l) => {
+		let w = match self.var(i) {
+		    Type::Int(w) => Some (w),
+		    t => {
+			self.diagnostics.push(
+			    error(format!("Cannot count with {} of type {}", i, t), *l,
+				  "counts with a non-integer"));
+			None
+		    }
+		};
+		self.aexpr(e1, w);
+		self.aexpr(e2, w);
+		self.cmd(c)
+	    }
+	    Assert (e, _, _) | Assume (e, _, _) => self.bexpr(e),
+	    Let (x, e, c, l) => {
+		let t = self.expr(e, None);
+		if t == Type::Array {
+		    self.diagnostics.push(
+			error(format!("{} cannot be bound to an array", x), *l, "binds an array"))
+		}
+		self.locals.push((x.clone(), t));
+		self.cmd(c);
+		self.locals.pop();
+	    }
+	    Return (e, _) => { self.aexpr(e, Some (Width::Int)); }
+	}
+    }
+
+    fn proc_body(&mut self, params: &[String], f: impl FnOnce(&mut Self)) {
+	self.locals = params.iter().map(|x| (x.clone(), Type::Int(Width::Int))).collect();
+	f(self);
+	self.locals.clear()
+    }
+}
+
+/// Checks the types in `p`, and annotates its arithmetic with widths.
+pub fn check(p: &mut Program) -> Result<(), Vec<Diagnostic>> {
+    let mut c = Checker {
+	globals: HashMap::new(),
+	locals: Vec::new(),
+	changed: true,
+	diagnostics: Vec::new()
+    };
+    for proc in &p.procs {
+	c.declare(&proc.body)
+    }
+    c.declare(&p.main);
+    while c.changed {
+	c.changed = false;
+	for proc in &p.procs {
+	    c.proc_body(&proc.params, |c| c.infer(&proc.body))
+	}
+	c.infer(&p.main)
+    }
+    for proc in &mut p.procs {
+	let body = &mut proc.body;
+	c.proc_body(&proc.params, |c| c.cmd(body))
+    }
+    c.cmd(&mut p.main);
+    if c.diagnostics.is_empty() { Ok (()) } else { Err (c.diagnostics) }
+}
@@ -0,0 +1,53 @@
+use codespan::{CodeMap, FileName};
+use imp::run;
+
+// Warnings about variables that may be used before they are assigned.
+
+// The warnings for `src`, which must pass the other checks.
+fn warnings(src: &str) -> Vec<String> {
+    let mut codemap = CodeMap::new();
+    let file = codemap.add_filemap(FileName::virtual_(String::from("test.imp")), String::from(src));
+    let mut p = run::parse(&file).unwrap();
+    run::check(&mut p).unwrap().into_iter().map(|d| d.message).collect()
+}
+
+fn unassigned(x: &str) -> Vec<String> {
+    vec![format!("{} may be used before it is assigned", x)]
+}
+
+#[test]
+fn both_branches_of_an_if_must_assign() {
+    assert!(warnings("read c; if c >? 0 { x := 1 } else { x := 2 }; print x").is_empty());
+    assert_eq!(warnings("read c; if c >? 0 { x := 1 } else { skip }; print x"), unassigned("x"));
+    assert_eq!(warnings("print y"), ["y is never assigned"]);
+}
+
+#[test]
+fn while_bodies_may_not_run() {
+    assert_eq!(warnings("read c; while c >? 0 { x := 1; c := c - 1 }; print x"), unassigned("x"));
+    assert!(warnings("read c; x := 0; while c >? 0 { x := x + 1; c := c - 1 }; print x").is_empty());
+    assert!(warnings("read c; do { x := 1; c := c - 1 } while c >? 0; print x").is_empty());
+}
+
+#[test]
+fn breaks_leave_with_what_they_assigned() {
+    assert_eq!(warnings("read c; do { if c >? 0 { break } else { skip }; x := 1 } while false; print x"),
+	       unassigned("x"));
+    assert!(warnings("read c; do { x := 1; if c >? 0 { break } else { skip } } while false; print x")
+	    .is_empty());
+    // The body may continue before assigning, and the condition sees that.
+    assert_eq!(warnings("read c; do { if c >? 0 { continue } else { skip }; x := 1 } while x >? 0"),
+	       unassigned("x"));
+}
+
+#[test]
+fn let_bindings_end_with_their_body() {
+    assert!(warnings("let x := 1 in { print x }").is_empty());
+    assert_eq!(warnings("let x := 1 in { skip }; print x"), unassigned("x"));
+    assert!(warnings("x := 0; let x := 1 in { skip }; print x").is_empty());
+    // A break inside the body leaves the binding behind.
+    assert_eq!(warnings("do { let x := 1 in { break } } while false; print x"), unassigned("x"));
+    // A break before the body keeps what it had.
+    assert!(warnings("read c; do { if c >? 0 { x := 1; break } else { skip };
+		      let x := 0 in { skip }; x := 2 } while false; print x").is_empty());
+}
@@ -0,0 +1,46 @@
+use codespan::{ByteOffset, ByteSpan, CodeMap, FileName};
+use imp::diagnostic::Diagnostic;
+
+// How diagnostics are rendered against their source.
+
+// `d` rendered for a file holding `src`,
+// with `d` built from that file's start.
+fn rendered(src: &str, d: impl FnOnce(codespan::ByteIndex) -> Diagnostic) -> String {
+    let mut codemap = CodeMap::new();
+    let file = codemap.add_filemap(FileName::virtual_(String::from("test.imp")), String::from(src));
+    let mut out = Vec::new();
+    d(file.span().start()).render(&codemap, &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+fn span(start: codespan::ByteIndex, from: i64, to: i64) -> ByteSpan {
+    ByteSpan::new(start + ByteOffset (from), start + ByteOffset (to))
+}
+
+#[test]
+fn labels_are_underlined_in_their_line() {
+    let src = "x := 1;\nif x >? 0 {\n\ty := x / 0\n} else { skip }";
+    let out = rendered(src, |s| Diagnostic::error("Division by zero in 1 / 0")
+		       .with_label(span(s, 26, 31), "divides by zero")
+		       .with_secondary(span(s, 0, 6), "x is set here")
+		       .with_note("store: {x = 1}"));
+    assert_eq!(out, "error: Division by zero in 1 / 0\n \
+		     --> <test.imp>:3:7\n  \
+		     |\n\
+		     3 | \ty := x / 0\n  \
+		     | \t     ^^^^^ divides by zero\n\
+		     1 | x := 1;\n  \
+		     | ------ x is set here\n  \
+		     = note: store: {x = 1}\n");
+}
+
+#[test]
+fn carets_stop_at_the_end_of_the_line() {
+    let src = (1..=9).map(|_| "skip;\n").collect::<String>() + "while true {\n  skip\n}";
+    let out = rendered(&src, |s| Diagnostic::warning("Loop never ends").with_label(span(s, 60, 75), ""));
+    assert_eq!(out, "warning: Loop never ends\n  \
+		     --> <test.imp>:10:7\n   \
+		     |\n\
+		     10 | while true {\n   \
+		     |       ^^^^^^\n");
+}
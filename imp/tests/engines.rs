@@ -0,0 +1,529 @@
+use std::time::Duration;
+use imp::{integer::Integer, limits::Limits, op::{Logic, Overflow}, run::{self, Engine, Options, Outcome}};
+
+// Both engines, with and without constant folding,
+// must print the same values and fail with the same errors.
+
+// Runs `src` on both engines, with and without folding.
+fn runs(src: &str, options: &Options) -> Vec<Outcome> {
+    let mut outcomes = Vec::new();
+    for &engine in &[Engine::Eval, Engine::Step] {
+	for &fold in &[false, true] {
+	    let options = Options { engine, fold, ..options.clone() };
+	    outcomes.push(run::run_source("test.imp", src, &options))
+	}
+    }
+    outcomes
+}
+
+// The values a run printed and the errors it raised.
+fn seen(o: &Outcome) -> (Vec<String>, Vec<String>) {
+    let printed = o.printed.iter().map(|v| v.to_string()).collect();
+    let errors = o.diagnostics.iter()
+	.filter(|d| d.is_error())
+	.map(|d| d.message.clone())
+	.collect();
+    (printed, errors)
+}
+
+// What every run of `src` shows, checking that they agree.
+fn agreed(src: &str, options: &Options) -> (Vec<String>, Vec<String>) {
+    let outcomes = runs(src, options);
+    let first = seen(&outcomes[0]);
+    for o in &outcomes[1..] {
+	assert_eq!(seen(o), first, "runs of {:?} disagree", src)
+    }
+    first
+}
+
+// The values `src` prints, checking that it does not fail.
+fn prints(src: &str, options: &Options) -> Vec<String> {
+    let (printed, errors) = agreed(src, options);
+    assert!(errors.is_empty(), "{:?} failed: {:?}", src, errors);
+    printed
+}
+
+// The values `src` prints before it fails, and the error.
+fn fails(src: &str, options: &Options) -> (Vec<String>, String) {
+    let (printed, mut errors) = agreed(src, options);
+    assert_eq!(errors.len(), 1, "{:?} raised {:?}", src, errors);
+    (printed, errors.pop().unwrap())
+}
+
+fn with_overflow(overflow: Overflow) -> Options {
+    Options { overflow, ..Options::default() }
+}
+
+const EDGES: &str = "x : i8; x := 127; y : i8; y := -128; u : u8; u := 0;";
+
+#[test]
+fn overflow_traps_at_the_width_edges() {
+    let trap = with_overflow(Overflow::Trap);
+    for (e, message) in &[
+	("x + 1", "Integer overflow in 127 + 1"),
+	("y - 1", "Integer overflow in -128 - 1"),
+	("x * 2", "Integer overflow in 127 * 2"),
+	("y / -1", "Integer overflow in -128 / -1"),
+	("-y", "Integer overflow in -(-128)"),
+	("u - 1", "Integer overflow in 0 - 1"),
+    ] {
+	let src = format!("{} print x; print {}", EDGES, e);
+	assert_eq!(fails(&src, &trap), (vec![String::from("127")], String::from(*message)))
+    }
+    assert_eq!(prints(&format!("{} print x - 1; print y + 127; print u + 255", EDGES), &trap),
+	       ["126", "-1", "255"]);
+}
+
+#[test]
+fn overflow_wraps_and_saturates_at_the_width_edges() {
+    let src = format!("{} print x + 1; print y - 1; print x * 2; print y / -1; print -y; print u - 1", EDGES);
+    assert_eq!(prints(&src, &with_overflow(Overflow::Wrap)),
+	       ["-128", "127", "-2", "-128", "-128", "255"]);
+    assert_eq!(prints(&src, &with_overflow(Overflow::Saturate)),
+	       ["127", "-128", "127", "127", "127", "0"]);
+}
+
+#[test]
+fn folding_keeps_constant_overflow() {
+    let src = "x : i8; x := 100 + 100; print x";
+    assert_eq!(fails(src, &with_overflow(Overflow::Trap)).1, "Integer overflow in 100 + 100");
+    assert_eq!(prints(src, &with_overflow(Overflow::Wrap)), ["-56"]);
+    assert_eq!(prints(src, &with_overflow(Overflow::Saturate)), ["127"]);
+}
+
+#[test]
+fn literals_must_fit_their_type() {
+    let options = Options::default();
+    assert_eq!(agreed("x : i8; x := -129", &options).1, ["Literal -129 does not fit in i8"]);
+    assert_eq!(prints("y : i64; y := 3000000000; print y", &options), ["3000000000"]);
+    if cfg!(not(feature = "bigint")) {
+	assert_eq!(agreed("x := 3000000000", &options).1, ["Literal 3000000000 does not fit in int"]);
+	assert_eq!(prints("x := 2147483647; print x", &options), ["2147483647"]);
+	let o = run::run_source("test.imp", "x : i64; x := 99999999999999999999", &options);
+	let error = o.diagnostics.iter().find(|d| d.is_error()).unwrap();
+	assert_eq!(error.notes, ["no integer type is wider than i64 unless built with the bigint feature"]);
+    }
+}
+
+#[test]
+fn comparisons_at_and_around_equality() {
+    let src = "read x; read y; print x <? y; print x <=? y; print x >? y; print x >=? y; print x =? y; print x !=? y";
+    for (input, printed) in &[
+	("1,2", ["true", "true", "false", "false", "false", "true"]),
+	("2,2", ["false", "true", "false", "true", "true", "false"]),
+	("-1,-2", ["false", "false", "true", "true", "false", "true"]),
+    ] {
+	assert_eq!(prints(src, &with_input(input)), printed)
+    }
+    assert_eq!(prints("print 1 <=? 2; print 2 >=? 3; print 3 !=? 3", &Options::default()),
+	       ["true", "false", "false"]);
+}
+
+#[test]
+fn int_grows_without_bound_only_with_bigint() {
+    let src = "read n; r := 1; while n >? 1 { r := r * n; n := n - 1 }; print r";
+    if cfg!(feature = "bigint") {
+	assert_eq!(prints(src, &with_input("30")), ["265252859812191058636308480000000"]);
+	assert_eq!(prints("x := 99999999999999999999; print x + 1", &Options::default()),
+		   ["100000000000000000000"]);
+    } else {
+	assert_eq!(prints(src, &with_input("12")), ["479001600"]);
+	assert_eq!(fails(src, &with_input("13")).1, "Integer overflow in 1037836800 * 3");
+    }
+}
+
+#[test]
+fn division_by_zero_fails() {
+    let options = Options::default();
+    assert_eq!(fails("x := 7; z := 0; print x / 2; print x / z", &options),
+	       (vec![String::from("3")], String::from("Division by zero in 7 / 0")));
+    assert_eq!(fails("x := -7; z := x - x; print x % 2; print x % z", &options),
+	       (vec![String::from("-1")], String::from("Division by zero in -7 % 0")));
+}
+
+#[test]
+fn folding_keeps_division_by_zero() {
+    let options = Options::default();
+    for (src, message) in &[
+	("print 1; print 7 / 0", "Division by zero in 7 / 0"),
+	("print 1; print 7 % (3 - 3)", "Division by zero in 7 % 0"),
+	("print 1; x := 2; print 0 * (x / 0)", "Division by zero in 2 / 0"),
+	("print 1; x := 2; print x / 0 - x / 0", "Division by zero in 2 / 0"),
+	("print 1; x := 2; print (x % 0) % 1", "Division by zero in 2 % 0"),
+	("print 1; if 1 / 0 >? 0 { skip } else { skip }", "Division by zero in 1 / 0"),
+    ] {
+	assert_eq!(fails(src, &options), (vec![String::from("1")], String::from(*message)))
+    }
+    for &ov in &[Overflow::Wrap, Overflow::Saturate] {
+	assert_eq!(fails("print 7 / 0", &with_overflow(ov)).1, "Division by zero in 7 / 0")
+    }
+}
+
+#[test]
+fn long_programs_do_not_grow_the_stack() {
+    // Far deeper than a test thread's stack allows
+    // if any pass recursed once per command.
+    let src = (0..50_000).map(|i| format!("x := {};\n", i)).collect::<String>() + "print x";
+    assert_eq!(prints(&src, &Options::default()), ["49999"]);
+    let body = (0..50_000).map(|i| format!("x := {};\n", i)).collect::<String>();
+    let src = format!("proc f(n) {{ {} return x + n }} x := 0; print f(1)", body);
+    // The step engine copies the body of each call, which still recurses.
+    for &fold in &[false, true] {
+	let o = run::run_source("test.imp", &src, &Options { fold, ..Options::default() });
+	assert_eq!(seen(&o), (vec![String::from("50000")], vec![]));
+    }
+}
+
+#[test]
+fn long_loops_do_not_grow_the_stack() {
+    // Each iteration would take a frame or more if loops recursed.
+    let src = "read n; s := 0; while n >? 0 { s := s + n % 3; n := n - 1 }; print s;
+	       for i := 1 to 100000 { if i % 2 =? 0 { continue } else { s := s - 1 } }; print s";
+    assert_eq!(prints(src, &with_input("100000")), ["100000", "50000"]);
+}
+
+fn with_limits(limits: Limits) -> Options {
+    Options { limits, ..Options::default() }
+}
+
+#[test]
+fn output_limit_keeps_what_ran() {
+    let src = "x := 1; print x; x := 2; print x; x := 3; print x; x := 4";
+    let options = with_limits(Limits { output: Some (2), ..Limits::default() });
+    assert_eq!(fails(src, &options),
+	       (vec![String::from("1"), String::from("2")],
+		String::from("Exceeded the output limit of 2 lines")));
+    for o in runs(src, &options) {
+	assert_eq!(o.store.get("x"), Some (Integer::from(3)));
+	let error = o.diagnostics.iter().find(|d| d.is_error()).unwrap();
+	assert!(error.notes[0].ends_with("and 2 printed lines"), "{:?}", error.notes);
+	assert_eq!(error.notes[1], "store: {x = 3}");
+    }
+}
+
+#[test]
+fn step_limit_stops_a_loop() {
+    let src = "x := 0; print x; while true { x := x + 1 }";
+    let options = with_limits(Limits { steps: Some (1000), ..Limits::default() });
+    assert_eq!(fails(src, &options),
+	       (vec![String::from("0")], String::from("Exceeded the step limit of 1000")));
+    for o in runs(src, &options) {
+	assert_eq!(o.steps, 1000);
+	assert!(o.store.get("x") > Some (Integer::from(0)));
+    }
+}
+
+#[test]
+fn store_limit_counts_array_elements() {
+    let src = "x := 1; print x; array a[10]; print 2";
+    let options = with_limits(Limits { store: Some (5), ..Limits::default() });
+    assert_eq!(fails(src, &options),
+	       (vec![String::from("1")], String::from("Exceeded the store limit of 5 values")));
+    for o in runs(src, &options) {
+	assert_eq!(o.store.len(), 1);
+	assert!(o.store.lookup("a").is_none());
+    }
+    assert_eq!(prints("array a[4]; print len(a)", &options), ["4"]);
+}
+
+#[test]
+fn time_limit_stops_a_loop() {
+    let options = with_limits(Limits { time: Some (Duration::from_millis(10)), ..Limits::default() });
+    assert_eq!(fails("print 1; while true { skip }", &options),
+	       (vec![String::from("1")], String::from("Exceeded the time limit of 10ms")));
+}
+
+fn with_input(input: &str) -> Options {
+    Options { input: input.parse().unwrap(), ..Options::default() }
+}
+
+#[test]
+fn read_takes_the_input_in_order() {
+    let src = "read x; read y; print x - y";
+    assert_eq!(prints(src, &with_input("5, 3")), ["2"]);
+    assert_eq!(prints(src, &with_input("3,5,7")), ["-2"]);
+}
+
+#[test]
+fn read_fails_when_the_input_runs_out() {
+    let src = "n := 0; read x; while true { print x; n := n + 1; read x }";
+    let (printed, error) = fails(src, &with_input("4,-1"));
+    assert_eq!(printed, ["4", "-1"]);
+    assert_eq!(error, "No input left to read into x");
+    for o in runs(src, &with_input("4,-1")) {
+	assert_eq!(o.store.get("n"), Some (Integer::from(2)))
+    }
+    assert_eq!(fails("read x; print x", &with_input("")).1, "No input left to read into x");
+}
+
+#[test]
+fn read_rejects_values_out_of_range() {
+    let src = "x : u8; read x; print x";
+    assert_eq!(prints(src, &with_input("255")), ["255"]);
+    for &ov in &[Overflow::Trap, Overflow::Wrap, Overflow::Saturate] {
+	let options = Options { overflow: ov, ..with_input("256") };
+	assert_eq!(fails(src, &options).1, "Value 256 read into x does not fit in u8")
+    }
+}
+
+#[test]
+fn let_shadows_and_restores_on_exit() {
+    let src = "x := 1; let x := x + 1 in { print x; x := 3; print x; let x := 4 in { print x }; print x };
+	       print x; let y := 5 in { x := y }; print x";
+    assert_eq!(prints(src, &Options::default()), ["2", "3", "4", "3", "1", "5"]);
+    for o in runs(src, &Options::default()) {
+	assert_eq!(o.store.to_string(), "{x = 5}")
+    }
+    // The binding ends with its body, even when it shadowed nothing.
+    assert_eq!(fails("let y := 1 in { print y }; print y", &Options::default()),
+	       (vec![String::from("1")], String::from("Unbound Variable y")));
+}
+
+#[test]
+fn recursive_calls_keep_their_locals() {
+    let src = "proc f(n) { if n <? 1 { return 0 } else {
+		   let t := n in { let r := f(n - 1) in { return t + r } } } }
+	       print f(3)";
+    assert_eq!(prints(src, &Options::default()), ["6"]);
+    let src = "proc f(n) { if n <? 1 { return 0 } else { t := n; r := f(n - 1); return t + r } }
+	       print f(3)";
+    let errors = agreed(src, &Options::default()).1;
+    assert_eq!(errors, ["t is assigned in a procedure, but is not a parameter, local or global",
+			"r is assigned in a procedure, but is not a parameter, local or global"]);
+}
+
+#[test]
+fn procedures_assign_globals() {
+    let src = "proc count(n) { calls := calls + 1; n := n + 1; return n }
+	       calls := 0; print count(count(1)); print calls";
+    assert_eq!(prints(src, &Options::default()), ["3", "2"]);
+}
+
+#[test]
+fn call_depth_is_limited_before_the_stack_overflows() {
+    let src = "proc f(n) { return f(n) } print f(1)";
+    assert_eq!(fails(src, &Options::default()).1, "Exceeded the call depth limit of 100");
+    let src = "proc f(n) { if n <? 1 { return 0 } else { let t := n in { return t + f(n - 1) } } }
+	       print f(99); print f(100)";
+    assert_eq!(fails(src, &Options::default()),
+	       (vec![String::from("4950")], String::from("Exceeded the call depth limit of 100")));
+    let options = with_limits(Limits { depth: Some (3), ..Limits::default() });
+    assert_eq!(fails(src, &options).1, "Exceeded the call depth limit of 3");
+}
+
+#[test]
+fn raised_call_depth_limits_get_the_stack_they_need() {
+    // Far deeper than a test thread's stack holds.
+    // The step engine takes longer for each call, so it goes less deep.
+    let src = "proc f(n) { if n <? 1 { return 0 } else { return f(n - 1) + 1 } } print f(N)";
+    for &(engine, n) in &[(Engine::Eval, 5000), (Engine::Step, 500)] {
+	for &fold in &[false, true] {
+	    let options = Options {
+		engine, fold, ..with_limits(Limits { depth: Some (2 * n), ..Limits::default() })
+	    };
+	    let o = run::run_source("test.imp", &src.replace('N', &n.to_string()), &options);
+	    assert_eq!(seen(&o), (vec![n.to_string()], vec![]));
+	    let options = Options { limits: Limits { depth: Some (n - 1), ..Limits::default() }, ..options };
+	    let o = run::run_source("test.imp", &src.replace('N', &n.to_string()), &options);
+	    assert_eq!(seen(&o).1, [format!("Exceeded the call depth limit of {}", n - 1)]);
+	}
+    }
+}
+
+#[test]
+fn errors_leave_the_store_of_the_main_program() {
+    let src = "proc f(n) { let t := n in { return t / 0 } }
+	       x := 1; let y := 2 in { x := f(x + y) }";
+    assert_eq!(fails(src, &Options::default()).1, "Division by zero in 3 / 0");
+    for o in runs(src, &Options::default()) {
+	assert_eq!(o.store.to_string(), "{x = 1}")
+    }
+}
+
+#[test]
+fn unbound_variables_are_reported_where_they_are_used() {
+    let src = "proc f(n) {\n  return n + y\n}\nprint f(1)";
+    assert_eq!(fails(src, &Options::default()).1, "Unbound Variable y");
+    for o in runs(src, &Options::default()) {
+	let error = o.diagnostics.iter().find(|d| d.is_error()).unwrap();
+	let start = error.labels[0].span.start();
+	let (line, col) = o.codemap.find_file(start).unwrap().location(start).unwrap();
+	assert_eq!(format!("{}:{}", line.number(), col.number()), "2:14");
+	let mut rendered = Vec::new();
+	error.render(&o.codemap, &mut rendered).unwrap();
+	assert!(String::from_utf8(rendered).unwrap().contains("--> <test.imp>:2:14"));
+    }
+}
+
+#[test]
+fn bad_array_lengths_are_reported_with_their_value() {
+    for (n, message) in &[
+	("-1", "Invalid array length -1"),
+	("-7", "Invalid array length -7"),
+    ] {
+	let src = format!("print 1; n := {}; array a[n]", n);
+	assert_eq!(fails(&src, &Options::default()), (vec![String::from("1")], String::from(*message)));
+	for o in runs(&src, &Options::default()) {
+	    let error = o.diagnostics.iter().find(|d| d.is_error()).unwrap();
+	    assert_eq!(error.labels[0].message, format!("{} is not a valid array length", n))
+	}
+    }
+}
+
+#[test]
+fn arrays_cannot_be_assigned() {
+    for src in &["array a[2]; array b[2]; b := a",
+		 "array a[2]; b := a",
+		 "array a[2]; x := 1; x := a",
+		 "array a[2]; x := 1; x, y := a, 2",
+		 "array b[2]; b := 1"] {
+	assert_eq!(agreed(src, &Options::default()).1, ["Cannot assign an array"], "{:?}", src)
+    }
+    assert_eq!(prints("array a[2]; a[1] := 3; x := a[1]; print x", &Options::default()), ["3"]);
+}
+
+#[test]
+fn assertions_quote_the_condition_as_written() {
+    let src = "x := 3; assert x >? 0 and (x<?3 or false); print x";
+    assert_eq!(fails(src, &Options::default()).1, "Assertion x >? 0 and (x<?3 or false) failed");
+    let src = "x := 3; assume x<=?2; print x";
+    for o in runs(src, &Options::default()) {
+	assert!(o.printed.is_empty());
+	assert_eq!(o.diagnostics[0].message, "Infeasible path: assumed x<=?2, which does not hold")
+    }
+}
+
+#[test]
+fn break_and_continue_in_for() {
+    let src = "for i := 1 to 5 {
+		   if i =? 2 { continue } else { skip };
+		   if i =? 4 { break } else { skip };
+		   print i };
+	       print i";
+    assert_eq!(prints(src, &Options::default()), ["1", "3", "4"]);
+    assert_eq!(prints("for i := 3 to 1 { print i }; print 7", &Options::default()), ["7"]);
+}
+
+#[test]
+fn break_and_continue_in_do_while() {
+    let src = "x := 0;
+	       do { x := x + 1;
+		    if x =? 2 { continue } else { skip };
+		    print x;
+		    if x >=? 4 { break } else { skip } } while x <? 10;
+	       print x";
+    assert_eq!(prints(src, &Options::default()), ["1", "3", "4", "4"]);
+    // `continue` goes on to the condition, which ends the loop.
+    assert_eq!(prints("x := 0; do { x := x + 1; continue } while x <? 3; print x", &Options::default()),
+	       ["3"]);
+}
+
+#[test]
+fn break_leaves_only_the_innermost_loop() {
+    let src = "for i := 1 to 3 { for j := 1 to 3 { if j =? 2 { break } else { skip }; print 10 * i + j } }";
+    assert_eq!(prints(src, &Options::default()), ["11", "21", "31"]);
+    let src = "x := 0; while x <? 2 { x := x + 1; do { break } while true; print x }";
+    assert_eq!(prints(src, &Options::default()), ["1", "2"]);
+}
+
+#[test]
+fn break_outside_a_loop_is_rejected() {
+    for src in &["print 1; break", "proc f(n) { continue; return n } x := 0; while x <? 1 { x := f(1) }"] {
+	let (printed, errors) = agreed(src, &Options::default());
+	assert!(printed.is_empty());
+	assert_eq!(errors.len(), 1, "{:?}", errors);
+	assert!(errors[0].ends_with("outside of a loop"), "{:?}", errors);
+    }
+}
+
+#[test]
+fn parallel_assignment_reads_the_old_store() {
+    let src = "x := 1; y := 2; x, y := y, x; print x; print y";
+    assert_eq!(prints(src, &Options::default()), ["2", "1"]);
+    let src = "y := 0; p := 1; for i := 1 to 6 { y, p := p, y + p }; print y";
+    assert_eq!(prints(src, &Options::default()), ["8"]);
+    // The right-hand sides see `x` as it was before the assignment.
+    let src = "x := 3; x, y := 5, x; print y";
+    assert_eq!(prints(src, &Options::default()), ["3"]);
+}
+
+#[test]
+fn parallel_assignment_fails_before_any_write() {
+    let src = "x := 1; x, y := 2, 1 / 0";
+    assert_eq!(fails(src, &Options::default()).1, "Division by zero in 1 / 0");
+    for o in runs(src, &Options::default()) {
+	assert_eq!(o.store.to_string(), "{x = 1}")
+    }
+}
+
+#[test]
+fn parallel_assignment_is_checked_when_parsed() {
+    let errors = agreed("x, y := 1", &Options::default()).1;
+    assert_eq!(errors, ["Assignment to 2 variables needs 2 values, found 1."]);
+    let errors = agreed("x, x := 1, 2", &Options::default()).1;
+    assert_eq!(errors, ["x is assigned twice at once."]);
+}
+
+#[test]
+fn conditional_expressions_pick_a_branch() {
+    let src = "read x; print if x >? 0 then 1 else if x <? 0 then -1 else 0";
+    assert_eq!(prints(src, &with_input("5")), ["1"]);
+    assert_eq!(prints(src, &with_input("-5")), ["-1"]);
+    assert_eq!(prints(src, &with_input("0")), ["0"]);
+    assert_eq!(prints("print if true then 1 else 2; print if false then 1 else 2", &Options::default()),
+	       ["1", "2"]);
+}
+
+#[test]
+fn conditional_expressions_run_one_branch() {
+    let options = Options::default();
+    assert_eq!(prints("x := 0; print if x =? 0 then 1 else 1 / x", &options), ["1"]);
+    assert_eq!(prints("x := 0; print if x >? 0 then 1 / x else 2", &options), ["2"]);
+    assert_eq!(prints("print if true then 1 else 1 / 0", &options), ["1"]);
+    let src = "proc f(n) { calls := calls + 1; return n }
+	       calls := 0; print if calls =? 0 then f(1) else f(2); print calls";
+    assert_eq!(prints(src, &options), ["1", "1"]);
+    // Equal branches do not let the guard go unevaluated.
+    assert_eq!(fails("x := 0; print if 1 / x >? 0 then 1 else 1", &options).1, "Division by zero in 1 / 0");
+}
+
+#[test]
+fn conditional_expressions_take_as_much_to_their_right_as_they_can() {
+    let src = "read x; print if x >? 0 then 10 else 20 + 1";
+    assert_eq!(prints(src, &with_input("1")), ["10"]);
+    assert_eq!(prints(src, &with_input("0")), ["21"]);
+    let src = "read x; print (if x >? 0 then 10 else 20) + 1";
+    assert_eq!(prints(src, &with_input("1")), ["11"]);
+    // On the right of an operator, a conditional needs parentheses.
+    let errors = agreed("x := 0; print 1 + if x >? 0 then 1 else 2", &Options::default()).1;
+    assert_eq!(errors.len(), 1);
+    assert_eq!(prints("x := 0; print 1 + (if x >? 0 then 1 else 2 * 3)", &Options::default()), ["7"]);
+}
+
+fn with_logic(logic: Logic) -> Options {
+    Options { logic, ..Options::default() }
+}
+
+#[test]
+fn short_circuit_skips_the_right_operand() {
+    let src = "x := 0; print x =? 0 or y =? 1; print x =? 1 and 1 / x =? 0; print false and y =? 1";
+    assert_eq!(prints(src, &with_logic(Logic::Short)), ["true", "false", "false"]);
+    // The left operand is always evaluated.
+    assert_eq!(fails("x := 0; print (1 / x =? 0) or true", &with_logic(Logic::Short)).1,
+	       "Division by zero in 1 / 0");
+}
+
+#[test]
+fn strict_logic_evaluates_both_operands() {
+    let strict = with_logic(Logic::Strict);
+    for (src, message) in &[
+	("x := 0; print x =? 1; print x =? 0 or y =? 1", "Unbound Variable y"),
+	("x := 0; print x =? 1; print x =? 1 and 1 / x =? 0", "Division by zero in 1 / 0"),
+	("x := 0; print x =? 1; print false and y =? 1", "Unbound Variable y"),
+	("x := 0; print x =? 1; print true or y =? 1", "Unbound Variable y"),
+    ] {
+	assert_eq!(fails(src, &strict), (vec![String::from("false")], String::from(*message)))
+    }
+    assert_eq!(prints("x := 1; print x =? 1 and 2 / x =? 2; print x =? 0 or true", &strict),
+	       ["true", "true"]);
+}
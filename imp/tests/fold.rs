@@ -0,0 +1,136 @@
+use imp::{diagnostic::Diagnostic, input::Queue, run::{self, Engine, Options, Outcome}, store::Value, syntax::Program};
+
+// Constant folding must not change what a program prints,
+// nor whether and how it fails.
+
+// The values printed and the errors raised by a run.
+fn observe(src: &str, input: &str, engine: Engine, fold: bool) -> (Vec<Value>, Vec<String>) {
+    let options = Options {
+	fold,
+	engine,
+	input: input.parse::<Queue>().unwrap(),
+	..Options::default()
+    };
+    let Outcome { printed, diagnostics, .. } = run::run_source("test.imp", src, &options);
+    let errors = diagnostics.into_iter()
+	.filter(Diagnostic::is_error)
+	.map(|d| d.message)
+	.collect();
+    (printed, errors)
+}
+
+fn same_with_folding(src: &str, input: &str) -> (Vec<Value>, Vec<String>) {
+    let mut seen = Vec::new();
+    for engine in [Engine::Eval, Engine::Step].iter() {
+	let before = observe(src, input, *engine, false);
+	let after = observe(src, input, *engine, true);
+	assert_eq!(before, after, "folding changed the behaviour of {:?} under {:?}", src, engine);
+	seen.push(before)
+    }
+    assert_eq!(seen[0], seen[1], "the engines disagree on {:?}", src);
+    seen.pop().unwrap()
+}
+
+fn unbound(x: &str) -> Vec<String> {
+    vec![format!("Unbound Variable {}", x)]
+}
+
+#[test]
+fn erased_operands_still_fail() {
+    for src in &[
+	"print x - x",
+	"print 0 * x",
+	"print x * 0",
+	"print x % 1",
+	"print x =? x",
+	"print x <=? x",
+	"print (if y >? 0 then 1 else 1)",
+	"if x >? 0 { print 1 } else { print 1 }",
+	"let y := x in { skip }; print 2",
+	"b := true; print b and x >? 1 and false",
+	"n := 0; if n >? 0 { f := true } else { skip }; print f and false",
+	"f : bool; if f { print 1 } else { print 1 }",
+	"f : bool; print f or true",
+	"f : bool; print f =? f",
+    ] {
+	let (printed, errors) = same_with_folding(src, "");
+	assert!(printed.is_empty(), "{:?} printed {:?}", src, printed);
+	assert!(!errors.is_empty(), "{:?} did not fail", src);
+    }
+}
+
+#[test]
+fn only_some_paths_bind() {
+    let src = "read n; if n >? 0 { x := 1 } else { skip }; print 5; print x - x";
+    assert_eq!(same_with_folding(src, "1"), (vec![Value::Int(5.into()), Value::Int(0.into())], vec![]));
+    assert_eq!(same_with_folding(src, "0"), (vec![Value::Int(5.into())], unbound("x")));
+
+    let src = "read n; if n >? 0 { f := true } else { skip }; print 5; print f and false";
+    assert_eq!(same_with_folding(src, "1"), (vec![Value::Int(5.into()), Value::Bool(false)], vec![]));
+    assert_eq!(same_with_folding(src, "0"), (vec![Value::Int(5.into())], unbound("f")));
+
+    let src = "read n; while n >? 0 { x := n; n := n - 1 }; print x * 0";
+    assert_eq!(same_with_folding(src, "2").1, vec![] as Vec<String>);
+    assert_eq!(same_with_folding(src, "0").1, unbound("x"));
+
+    let src = "proc f(a) { return a - a + g * 0 } g := 1; print f(3)";
+    assert_eq!(same_with_folding(src, "").0, vec![Value::Int(0.into())]);
+    let src = "proc f(a) { return a - a + g * 0 } print f(3)";
+    assert_eq!(same_with_folding(src, "").1, unbound("g"));
+}
+
+#[test]
+fn bound_operands_are_still_folded() {
+    let src = "x := 4; print x - x; print x =? x; if x >? 0 { skip } else { skip }";
+    let (printed, errors) = same_with_folding(src, "");
+    assert_eq!(printed, vec![Value::Int(0.into()), Value::Bool(true)]);
+    assert!(errors.is_empty());
+
+    assert_eq!(folded(src).to_string(), "x := 4;\nprint 0;\nprint true");
+}
+
+#[test]
+fn comparisons_of_an_expression_with_itself_are_folded() {
+    let src = "read x; print x <=? x; print x <? x; print x >=? x; print x >? x; print x =? x; print x !=? x";
+    assert_eq!(folded(src).to_string(),
+	       "read x;\nprint true;\nprint false;\nprint true;\nprint false;\nprint true;\nprint false");
+    assert_eq!(same_with_folding(src, "3").0,
+	       [true, false, true, false, true, false].iter().map(|&b| Value::Bool(b)).collect::<Vec<_>>());
+    // Unless evaluating it may fail.
+    assert_eq!(folded("read x; print x / 2 <=? x / 2").to_string(), "read x;\nprint ((x / 2) <=? (x / 2))");
+}
+
+// `src` checked and folded.
+fn folded(src: &str) -> Program {
+    let mut codemap = codespan::CodeMap::new();
+    let file = codemap.add_filemap(codespan::FileName::virtual_("test.imp".to_string()), src.to_string());
+    let mut p = run::parse(&file).unwrap();
+    run::check(&mut p).unwrap();
+    p.fold(Default::default(), Default::default())
+}
+
+#[test]
+fn assertions_folded_to_true_are_removed() {
+    let src = "x := 1; assert 1 <? 2 and true; assume 2 >? 1; print x";
+    let p = folded(src);
+    assert_eq!(p.to_string(), "x := 1;\nprint x");
+    assert!(p.refuted().is_empty());
+    assert_eq!(same_with_folding(src, ""), (vec![Value::Int(1.into())], vec![]));
+}
+
+#[test]
+fn assertions_folded_to_false_are_refuted() {
+    let src = "proc f(n) { assert 1 >? 2; return n }
+	       read x; assert x >? 0; while x >? 5 { assert 2 <? 1 or false; x := x - 1 }; print x";
+    let p = folded(src);
+    let warnings : Vec<_> = p.refuted().into_iter().map(|d| (d.is_error(), d.message)).collect();
+    assert_eq!(warnings, [(false, String::from("Assertion 1 >? 2 always fails")),
+			  (false, String::from("Assertion 2 <? 1 or false always fails"))]);
+    // They are kept, so they still fail when reached.
+    assert!(p.to_string().contains("assert false"));
+    assert_eq!(same_with_folding(src, "3"), (vec![Value::Int(3.into())], vec![]));
+    assert_eq!(same_with_folding(src, "7").1, ["Assertion 2 <? 1 or false failed"]);
+    let o = run::run_source("test.imp", src, &Options { fold: true, input: "7".parse().unwrap(), ..Options::default() });
+    let warnings = o.diagnostics.iter().filter(|d| !d.is_error()).count();
+    assert_eq!(warnings, 2);
+}
@@ -0,0 +1,76 @@
+use codespan::{CodeMap, FileName};
+use imp::{lexer::{self, Lexer}, run::{self, Options}, syntax::Program};
+
+// Tokens and the source text their spans cover.
+fn lexed(src: &str) -> Vec<(String, String)> {
+    let mut codemap = CodeMap::new();
+    let file = codemap.add_filemap(FileName::virtual_(String::from("test.imp")), String::from(src));
+    let start = file.span().start();
+    lexer::tokenize(&file).unwrap().into_iter()
+	.map(|(l, t, r)| (t.to_string(), String::from(&src[(l - start).0 as usize..(r - start).0 as usize])))
+	.collect()
+}
+
+#[test]
+fn numerals_span_their_text() {
+    let tokens : Vec<_> = lexed("x := -007; print 007 - -0").into_iter().map(|(_, text)| text).collect();
+    assert_eq!(tokens, ["x", ":=", "-007", ";", "print", "007", "-", "-0"]);
+    assert_eq!(lexed("-007")[0].0, "-7");
+    let o = run::run_source("test.imp", "assert 9 <? 007", &Options::default());
+    assert_eq!(o.diagnostics[0].message, "Assertion 9 <? 007 failed");
+}
+
+#[test]
+fn comments_are_skipped_and_nest() {
+    let src = "x := 1; /* a /* nested */ comment */ print x // to the end of the line\n; print 2";
+    let tokens : Vec<_> = lexed(src).into_iter().map(|(_, text)| text).collect();
+    assert_eq!(tokens, ["x", ":=", "1", ";", "print", "x", ";", "print", "2"]);
+    let o = run::run_source("test.imp", src, &Options::default());
+    assert_eq!(o.printed.len(), 2);
+}
+
+#[test]
+fn comments_can_be_kept() {
+    let mut codemap = CodeMap::new();
+    let src = "x := 1 /* a /* nested */ comment */ // line\n";
+    let file = codemap.add_filemap(FileName::virtual_(String::from("test.imp")), String::from(src));
+    let tokens : Vec<_> = Lexer::with_comments(&file).map(|t| t.unwrap().1.to_string()).collect();
+    assert_eq!(tokens, ["x", ":=", "1", "/* a /* nested */ comment */", "// line"]);
+}
+
+#[test]
+fn unterminated_comments_are_lexical_errors() {
+    for src in &["print 1 /* open", "print 1 /* open /* nested */ still open"] {
+	let mut codemap = CodeMap::new();
+	let file = codemap.add_filemap(FileName::virtual_(String::from("test.imp")), String::from(*src));
+	let error = lexer::tokenize(&file).err().unwrap();
+	assert_eq!(error.message, "Unterminated block comment.");
+	// The label points at the outermost comment.
+	assert_eq!(error.labels[0].span.start() - file.span().start(), codespan::ByteOffset (8));
+    }
+}
+
+// `src` parsed and checked.
+fn parsed(src: &str) -> Program {
+    let mut codemap = CodeMap::new();
+    let file = codemap.add_filemap(FileName::virtual_(String::from("test.imp")), String::from(src));
+    let mut p = run::parse(&file).unwrap();
+    run::check(&mut p).unwrap();
+    p
+}
+
+#[test]
+fn folded_negative_constants_print_and_parse_back() {
+    for (src, printed) in &[
+	("x := 0 - 5; print x", "x := -5;\nprint x"),
+	("x := 3; print x - (0 - 5)", "x := 3;\nprint (x - -5)"),
+	("x := 3; print -(0 - 5) * x", "x := 3;\nprint (5 * x)"),
+	("x := 3; print -(x - 8) - (2 - 9)", "x := 3;\nprint (-(x - 8) - -7)"),
+    ] {
+	let folded = parsed(src).fold(Default::default(), Default::default()).to_string();
+	assert_eq!(folded, *printed);
+	assert_eq!(parsed(&folded).to_string(), folded);
+	let before = run::run_source("test.imp", src, &Options::default()).printed;
+	assert_eq!(run::run_source("test.imp", &folded, &Options::default()).printed, before);
+    }
+}
@@ -0,0 +1,65 @@
+use std::io::{self, Write};
+use codespan::{CodeMap, FileName};
+use imp::{context::Context, diagnostic::Diagnostic, input::Queue, limits::Meter,
+	  output::{Output, Stdout, Writer}, run, store::{Store, Value}};
+
+// Library callers choose where `print` sends its values.
+
+// Runs `src` into `out`, on the big-step engine and then the small-step one.
+fn run_into(src: &str, out: &mut dyn Output) -> [Result<(), Diagnostic>; 2] {
+    let mut codemap = CodeMap::new();
+    let file = codemap.add_filemap(FileName::virtual_(String::from("test.imp")), String::from(src));
+    let mut p = run::parse(&file).unwrap();
+    run::check(&mut p).unwrap();
+    let (mut meter, mut inp) = (Meter::unlimited(), Queue::default());
+    let cx = Context::new(Default::default(), Default::default(), &p.procs, &mut meter, out, &mut inp);
+    let evaluated = p.main.eval(&mut Store::new(), cx);
+    let (mut meter, mut inp) = (Meter::unlimited(), Queue::default());
+    let cx = Context::new(Default::default(), Default::default(), &p.procs, &mut meter, out, &mut inp);
+    let mut main = p.main.clone();
+    let stepped = main.normalize(&mut Store::new(), cx);
+    [evaluated, stepped]
+}
+
+#[test]
+fn writers_get_a_line_per_value() {
+    let mut out = Writer(Vec::new());
+    for result in run_into("x := 2; print x; print x >? 1", &mut out).iter() {
+	assert!(result.is_ok())
+    }
+    assert_eq!(String::from_utf8(out.0).unwrap(), "2\ntrue\n2\ntrue\n");
+}
+
+#[test]
+fn closures_take_each_value() {
+    let mut seen = Vec::new();
+    for result in run_into("print 1; print 2", &mut |v: &Value| seen.push(v.to_string())).iter() {
+	assert!(result.is_ok())
+    }
+    assert_eq!(seen, ["1", "2", "1", "2"]);
+}
+
+struct Closed;
+
+impl Write for Closed {
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+	Err (io::Error::new(io::ErrorKind::BrokenPipe, "closed"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+	Ok (())
+    }
+}
+
+#[test]
+fn failed_writes_stop_the_program() {
+    for result in run_into("print 1; x := 1", &mut Writer(Closed)).iter() {
+	assert_eq!(result.as_ref().unwrap_err().message, "Could not print: closed")
+    }
+}
+
+#[test]
+fn stdout_keeps_the_command_line_prefix() {
+    assert_eq!(Stdout::default().prefix, "OUTPUT: ");
+    assert_eq!(Stdout::new("> ").prefix, "> ");
+}
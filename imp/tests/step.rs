@@ -0,0 +1,62 @@
+use codespan::{CodeMap, FileName};
+use imp::{context::Context, input::Queue, integer::Integer, limits::Meter, output::Collect,
+	  run, step::{Rule, Session}, store::{Store, Value}, syntax::Program};
+
+// Driving the small-step engine one configuration at a time.
+
+fn program(src: &str) -> Program {
+    let mut codemap = CodeMap::new();
+    let file = codemap.add_filemap(FileName::virtual_(String::from("test.imp")), String::from(src));
+    let mut p = run::parse(&file).unwrap();
+    run::check(&mut p).unwrap();
+    p
+}
+
+#[test]
+fn session_reports_each_rule_and_store() {
+    let mut p = program("x := 1; print x + 1");
+    let (mut meter, mut out, mut inp) = (Meter::unlimited(), Collect::default(), Queue::default());
+    let mut store = Store::new();
+    let cx = Context::new(Default::default(), Default::default(), &p.procs,
+			  &mut meter, &mut out, &mut inp);
+    let mut session = Session::new(&mut p.main, &mut store, cx);
+    let mut rules = Vec::new();
+    while let Some (step) = session.step() {
+	let step = step.unwrap();
+	assert_eq!(step.store.get("x"), Some (Integer::from(1)));
+	rules.push((step.rule, step.cmd.to_string()));
+    }
+    assert_eq!(session.steps(), 5);
+    assert_eq!(rules, [
+	(Rule::Assign, String::from("skip;\nprint (x + 1)")),
+	(Rule::Seq, String::from("print (x + 1)")),
+	(Rule::Var, String::from("print (1 + 1)")),
+	(Rule::Arith, String::from("print 2")),
+	(Rule::Print, String::from("skip")),
+    ]);
+    assert!(session.step().is_none());
+    assert_eq!(out.0, [Value::Int(Integer::from(2))]);
+}
+
+#[test]
+fn session_stops_at_an_error() {
+    let mut p = program("print 1; print 1 / 0; print 2");
+    let (mut meter, mut out, mut inp) = (Meter::unlimited(), Collect::default(), Queue::default());
+    let mut store = Store::new();
+    let cx = Context::new(Default::default(), Default::default(), &p.procs,
+			  &mut meter, &mut out, &mut inp);
+    let mut session = Session::new(&mut p.main, &mut store, cx);
+    let mut steps = 0;
+    let err = loop {
+	match session.step() {
+	    Some (Ok (_)) => steps += 1,
+	    Some (Err (err)) => break err,
+	    None => panic!("the division did not fail")
+	}
+    };
+    assert_eq!(err.to_string(), "Division by zero in 1 / 0");
+    assert_eq!(steps, 2);
+    assert!(session.step().is_none());
+    // The failing step leaves the term as it was.
+    assert_eq!(session.cmd().to_string(), "print (1 / 0);\nprint 2");
+}